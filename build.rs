@@ -0,0 +1,527 @@
+//! Generate the type stub `minishogilib.pyi` from the pyo3 definitions in `src`.
+//!
+//! The classes, functions and constants registered by the `#[pymodule]` in `src/lib.rs` are collected,
+//! and the Rust types of their arguments and return values are converted into Python types.
+//! The argument names given by `text_signature` are checked against the Rust signatures,
+//! so a stale `text_signature` fails the build.
+
+extern crate syn;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use syn::punctuated::Punctuated;
+
+const STUB_HEADER: &str =
+    "# This file is generated by build.rs from the pyo3 definitions. Do not edit it by hand.\n";
+
+/// A parameter exposed to Python.
+struct Parameter {
+    name: String,
+    python_type: String,
+    default: Option<String>,
+}
+
+/// The exposed items of a `#[pyclass]`.
+#[derive(Default)]
+struct Class {
+    text_signature: Option<String>,
+    attributes: std::vec::Vec<String>,
+    methods: std::vec::Vec<String>,
+}
+
+/// The parsed source files of the crate indexed by the module name, and the type aliases defined in them.
+struct Sources {
+    files: HashMap<String, syn::File>,
+    aliases: HashMap<String, syn::Type>,
+}
+
+impl Sources {
+    fn load(source_dir: &Path) -> Sources {
+        let mut files = HashMap::new();
+        let mut aliases = HashMap::new();
+
+        for entry in fs::read_dir(source_dir).unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.extension().is_none_or(|extension| extension != "rs") {
+                continue;
+            }
+
+            let source = fs::read_to_string(&path).unwrap();
+            let file = syn::parse_file(&source)
+                .unwrap_or_else(|error| panic!("failed to parse {}: {}", path.display(), error));
+
+            for item in file.items.iter() {
+                if let syn::Item::Type(item) = item {
+                    aliases.insert(item.ident.to_string(), (*item.ty).clone());
+                }
+            }
+
+            files.insert(path.file_stem().unwrap().to_string_lossy().to_string(), file);
+        }
+
+        Sources { files, aliases }
+    }
+
+    fn module(&self, name: &str) -> &syn::File {
+        self.files.get(name).unwrap_or_else(|| panic!("module `{}` is not found", name))
+    }
+
+    /// Convert a Rust type into the Python type which pyo3 converts it to or from.
+    fn python_type(
+        &self,
+        ty: &syn::Type,
+        class: &str,
+        typing: &mut std::vec::Vec<&'static str>,
+    ) -> String {
+        match ty {
+            syn::Type::Reference(reference) => self.python_type(&reference.elem, class, typing),
+            syn::Type::Paren(paren) => self.python_type(&paren.elem, class, typing),
+            syn::Type::Tuple(tuple) => {
+                if tuple.elems.is_empty() {
+                    return "None".to_string();
+                }
+
+                typing.push("Tuple");
+                let elements: std::vec::Vec<String> =
+                    tuple.elems.iter().map(|elem| self.python_type(elem, class, typing)).collect();
+
+                format!("Tuple[{}]", elements.join(", "))
+            }
+            syn::Type::Path(path) => {
+                let segment = path.path.segments.last().unwrap();
+                let name = segment.ident.to_string();
+
+                let argument = || match &segment.arguments {
+                    syn::PathArguments::AngleBracketed(arguments) => match arguments.args.first() {
+                        Some(syn::GenericArgument::Type(ty)) => ty.clone(),
+                        _ => panic!("`{}` has no type argument", name),
+                    },
+                    _ => panic!("`{}` has no type argument", name),
+                };
+
+                match name.as_str() {
+                    "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64"
+                    | "isize" => "int".to_string(),
+                    "f32" | "f64" => "float".to_string(),
+                    "bool" => "bool".to_string(),
+                    "str" | "String" => "str".to_string(),
+                    "Self" => class.to_string(),
+                    "Result" | "PyResult" => self.python_type(&argument(), class, typing),
+                    "Vec" => {
+                        typing.push("List");
+                        format!("List[{}]", self.python_type(&argument(), class, typing))
+                    }
+                    "Option" => {
+                        typing.push("Optional");
+                        format!("Optional[{}]", self.python_type(&argument(), class, typing))
+                    }
+                    _ => match self.aliases.get(&name) {
+                        Some(alias) => self.python_type(alias, class, typing),
+                        None => name,
+                    },
+                }
+            }
+            _ => panic!("unsupported type in the Python API: {:?}", ty),
+        }
+    }
+}
+
+/// Return the `key = "value"` and bare `key` arguments of the `#[pyo3(...)]` or `#[pyclass(...)]` attributes.
+fn pyo3_arguments(
+    attributes: &[syn::Attribute],
+    attribute_name: &str,
+) -> HashMap<String, Option<String>> {
+    let mut arguments = HashMap::new();
+
+    for attribute in attributes.iter().filter(|attribute| attribute.path.is_ident(attribute_name)) {
+        if attribute.tokens.is_empty() {
+            continue;
+        }
+
+        let exprs = attribute
+            .parse_args_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)
+            .unwrap_or_else(|error| {
+                panic!("failed to parse #[{}(...)]: {}", attribute_name, error)
+            });
+
+        for expr in exprs.iter() {
+            match expr {
+                syn::Expr::Assign(assign) => {
+                    let key = match &*assign.left {
+                        syn::Expr::Path(path) => path.path.get_ident().unwrap().to_string(),
+                        _ => continue,
+                    };
+                    let value = match &*assign.right {
+                        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(value), .. }) => {
+                            Some(value.value())
+                        }
+                        _ => None,
+                    };
+
+                    arguments.insert(key, value);
+                }
+                syn::Expr::Path(path) => {
+                    arguments.insert(path.path.get_ident().unwrap().to_string(), None);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    arguments
+}
+
+fn has_attribute(attributes: &[syn::Attribute], name: &str) -> bool {
+    attributes.iter().any(|attribute| attribute.path.is_ident(name))
+}
+
+/// Return the parameters of a `#[pymethods]` method or a `#[pyfunction]` for Python,
+/// checking that `text_signature` lists the same arguments as the Rust signature.
+fn parameters(
+    sources: &Sources,
+    qualified_name: &str,
+    class: &str,
+    signature: &syn::Signature,
+    text_signature: Option<&String>,
+    typing: &mut std::vec::Vec<&'static str>,
+) -> std::vec::Vec<Parameter> {
+    let mut parameters = std::vec::Vec::new();
+
+    for input in signature.inputs.iter() {
+        if let syn::FnArg::Typed(input) = input {
+            // The GIL token is supplied by pyo3 and is not an argument in Python.
+            if let syn::Type::Path(path) = &*input.ty {
+                if path.path.segments.last().unwrap().ident == "Python" {
+                    continue;
+                }
+            }
+
+            let name = match &*input.pat {
+                syn::Pat::Ident(pat) => pat.ident.to_string(),
+                _ => panic!("`{}` has an argument which is not an identifier", qualified_name),
+            };
+
+            parameters.push(Parameter {
+                name,
+                python_type: sources.python_type(&input.ty, class, typing),
+                default: None,
+            });
+        }
+    }
+
+    let text_signature = match text_signature {
+        Some(text_signature) => text_signature,
+        None if parameters.is_empty() => return parameters,
+        None => panic!("`{}` takes arguments but has no `text_signature`", qualified_name),
+    };
+
+    let names: std::vec::Vec<&str> = text_signature
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty() && *name != "$self")
+        .collect();
+
+    if names.len() != parameters.len() {
+        panic!(
+            "`text_signature` of `{}` does not match its arguments: {}",
+            qualified_name, text_signature
+        );
+    }
+
+    for (parameter, name) in parameters.iter_mut().zip(names) {
+        let mut parts = name.splitn(2, '=');
+
+        if parts.next().unwrap().trim() != parameter.name {
+            panic!(
+                "`text_signature` of `{}` does not match its arguments: {}",
+                qualified_name, text_signature
+            );
+        }
+
+        parameter.default = parts.next().map(|default| default.trim().to_string());
+    }
+
+    parameters
+}
+
+fn stub_function(
+    name: &str,
+    receiver: bool,
+    parameters: &[Parameter],
+    return_type: &str,
+) -> String {
+    let mut arguments: std::vec::Vec<String> = std::vec::Vec::new();
+
+    if receiver {
+        arguments.push("self".to_string());
+    }
+
+    for parameter in parameters.iter() {
+        arguments.push(match &parameter.default {
+            Some(default) => format!("{}: {} = {}", parameter.name, parameter.python_type, default),
+            None => format!("{}: {}", parameter.name, parameter.python_type),
+        });
+    }
+
+    format!("def {}({}) -> {}: ...", name, arguments.join(", "), return_type)
+}
+
+fn return_type(
+    sources: &Sources,
+    output: &syn::ReturnType,
+    class: &str,
+    typing: &mut std::vec::Vec<&'static str>,
+) -> String {
+    match output {
+        syn::ReturnType::Default => "None".to_string(),
+        syn::ReturnType::Type(_, ty) => sources.python_type(ty, class, typing),
+    }
+}
+
+fn collect_class(
+    sources: &Sources,
+    module: &str,
+    class_name: &str,
+    typing: &mut std::vec::Vec<&'static str>,
+) -> Class {
+    let mut class = Class::default();
+
+    for item in sources.module(module).items.iter() {
+        match item {
+            syn::Item::Struct(item)
+                if item.ident == class_name && has_attribute(&item.attrs, "pyclass") =>
+            {
+                class.text_signature = pyo3_arguments(&item.attrs, "pyclass")
+                    .remove("text_signature")
+                    .and_then(|value| value);
+
+                for field in item.fields.iter() {
+                    if pyo3_arguments(&field.attrs, "pyo3").contains_key("get") {
+                        class.attributes.push(format!(
+                            "{}: {}",
+                            field.ident.as_ref().unwrap(),
+                            sources.python_type(&field.ty, class_name, typing)
+                        ));
+                    }
+                }
+            }
+            syn::Item::Impl(item) if has_attribute(&item.attrs, "pymethods") => {
+                match &*item.self_ty {
+                    syn::Type::Path(path) if path.path.is_ident(class_name) => {}
+                    _ => continue,
+                }
+
+                for impl_item in item.items.iter() {
+                    let method = match impl_item {
+                        syn::ImplItem::Method(method) => method,
+                        _ => continue,
+                    };
+
+                    let arguments = pyo3_arguments(&method.attrs, "pyo3");
+                    let constructor = has_attribute(&method.attrs, "new");
+                    let name = if constructor {
+                        "__init__".to_string()
+                    } else {
+                        match arguments.get("name") {
+                            Some(Some(name)) => name.clone(),
+                            _ => method.sig.ident.to_string(),
+                        }
+                    };
+
+                    // The signature of the constructor is given by `text_signature` of the class.
+                    let text_signature = if constructor {
+                        class.text_signature.as_ref()
+                    } else {
+                        arguments.get("text_signature").and_then(|value| value.as_ref())
+                    };
+                    let qualified_name = format!("{}.{}", class_name, name);
+                    let parameters = parameters(
+                        sources,
+                        &qualified_name,
+                        class_name,
+                        &method.sig,
+                        text_signature,
+                        typing,
+                    );
+                    let returns = if constructor {
+                        "None".to_string()
+                    } else {
+                        return_type(sources, &method.sig.output, class_name, typing)
+                    };
+
+                    class.methods.push(stub_function(&name, true, &parameters, &returns));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    class
+}
+
+fn collect_function(
+    sources: &Sources,
+    module: &str,
+    rust_name: &str,
+    typing: &mut std::vec::Vec<&'static str>,
+) -> String {
+    for item in sources.module(module).items.iter() {
+        if let syn::Item::Fn(item) = item {
+            if item.sig.ident != rust_name || !has_attribute(&item.attrs, "pyfunction") {
+                continue;
+            }
+
+            let arguments = pyo3_arguments(&item.attrs, "pyo3");
+            let name = match arguments.get("name") {
+                Some(Some(name)) => name.clone(),
+                _ => rust_name.to_string(),
+            };
+            let text_signature = arguments.get("text_signature").and_then(|value| value.as_ref());
+            let parameters = parameters(sources, &name, "", &item.sig, text_signature, typing);
+            let returns = return_type(sources, &item.sig.output, "", typing);
+
+            return stub_function(&name, false, &parameters, &returns);
+        }
+    }
+
+    panic!("#[pyfunction] `{}::{}` is not found", module, rust_name);
+}
+
+/// Split a path such as `position::Position` into the module name and the item name.
+fn module_and_name(path: &syn::Path) -> (String, String) {
+    let names: std::vec::Vec<String> = path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string().trim_start_matches("r#").to_string())
+        .collect();
+
+    if names.len() != 2 {
+        panic!("`{}` is not registered as `module::name`", names.join("::"));
+    }
+
+    (names[0].clone(), names[1].clone())
+}
+
+fn generate_stub(sources: &Sources) -> String {
+    let mut typing = std::vec::Vec::new();
+    let mut constants = std::vec::Vec::new();
+    let mut classes = std::vec::Vec::new();
+    let mut functions = std::vec::Vec::new();
+
+    let module_function = sources
+        .module("lib")
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Fn(item) if has_attribute(&item.attrs, "pymodule") => Some(item),
+            _ => None,
+        })
+        .next()
+        .expect("#[pymodule] is not found in lib.rs");
+
+    for stmt in module_function.block.stmts.iter() {
+        let call = match stmt {
+            syn::Stmt::Semi(syn::Expr::Try(expr), _) => match &*expr.expr {
+                syn::Expr::MethodCall(call) => call,
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        match call.method.to_string().as_str() {
+            "add" => {
+                let name = match call.args.first() {
+                    Some(syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(name), .. })) => {
+                        name.value()
+                    }
+                    _ => panic!("the name of a module constant is not a string literal"),
+                };
+                // The constants of this module are integers except for the ones given by string macros such as `env!`.
+                let python_type = match call.args.iter().nth(1) {
+                    Some(syn::Expr::Macro(_))
+                    | Some(syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(_), .. })) => "str",
+                    _ => "int",
+                };
+
+                constants.push(format!("{}: {}", name, python_type));
+            }
+            "add_class" => {
+                let path =
+                    match call.turbofish.as_ref().and_then(|turbofish| turbofish.args.first()) {
+                        Some(syn::GenericMethodArgument::Type(syn::Type::Path(path))) => &path.path,
+                        _ => panic!("add_class is called without a class"),
+                    };
+                let (module, name) = module_and_name(path);
+                let class = collect_class(sources, &module, &name, &mut typing);
+
+                let mut lines = vec![format!("class {}:", name)];
+                lines.extend(class.attributes.iter().map(|attribute| format!("    {}", attribute)));
+                lines.extend(class.methods.iter().map(|method| format!("    {}", method)));
+
+                classes.push(lines.join("\n"));
+            }
+            "add_function" => {
+                let wrap = match call.args.first() {
+                    Some(syn::Expr::Try(expr)) => match &*expr.expr {
+                        syn::Expr::Macro(wrap) => wrap,
+                        _ => panic!("add_function is called without wrap_pyfunction!"),
+                    },
+                    _ => panic!("add_function is called without wrap_pyfunction!"),
+                };
+                let wrap_arguments = wrap
+                    .mac
+                    .parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)
+                    .unwrap();
+                let (module, name) = match wrap_arguments.first() {
+                    Some(syn::Expr::Path(path)) => module_and_name(&path.path),
+                    _ => panic!("wrap_pyfunction! is called without a function"),
+                };
+
+                functions.push(collect_function(sources, &module, &name, &mut typing));
+            }
+            _ => {}
+        }
+    }
+
+    typing.sort();
+    typing.dedup();
+
+    let mut stub = STUB_HEADER.to_string();
+
+    if !typing.is_empty() {
+        stub += &format!("from typing import {}\n", typing.join(", "));
+    }
+
+    stub += &format!("\n{}\n", constants.join("\n"));
+
+    for class in classes.iter() {
+        stub += &format!("\n{}\n", class);
+    }
+
+    stub += &format!("\n{}\n", functions.join("\n"));
+
+    stub
+}
+
+fn main() {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let manifest_dir = Path::new(&manifest_dir);
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src");
+
+    let stub = generate_stub(&Sources::load(&manifest_dir.join("src")));
+
+    // The stub is written next to Cargo.toml, where maturin picks it up for the wheel.
+    // It is rewritten only when it changes, so that unchanged builds do not touch the source tree.
+    let stub_path = manifest_dir.join("minishogilib.pyi");
+
+    if fs::read_to_string(&stub_path).ok().as_ref() != Some(&stub) {
+        fs::write(&stub_path, stub).unwrap();
+    }
+}
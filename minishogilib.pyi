@@ -0,0 +1,93 @@
+# This file is generated by build.rs from the pyo3 definitions. Do not edit it by hand.
+from typing import List, Optional, Tuple
+
+__version__: str
+HALFKP_FEATURE_NB: int
+SQUARE_NB: int
+REPETITION_NONE: int
+REPETITION_DRAW: int
+REPETITION_WIN: int
+REPETITION_LOSE: int
+
+class Position:
+    def __init__(self) -> None: ...
+    def __getstate__(self) -> str: ...
+    def __setstate__(self, sfen: str) -> None: ...
+    def copy(self, entire: bool) -> Position: ...
+    def print(self) -> None: ...
+    def sfen(self, history: bool) -> str: ...
+    def usi_position(self) -> str: ...
+    def get_kif(self) -> List[str]: ...
+    def get_csa_kif(self) -> List[str]: ...
+    def csa(self, history: bool) -> str: ...
+    def set_csa(self, csa: str) -> None: ...
+    def csa_to_move(self, csa: str) -> Move: ...
+    def _set_sfen_with_option(self, sfen: str, incremental_update: bool) -> None: ...
+    def set_sfen(self, sfen: str) -> None: ...
+    def set_sfen_simple(self, sfen: str) -> None: ...
+    def set_start_position(self) -> None: ...
+    def set_sfen_without_startpos(self, sfen: str) -> None: ...
+    def set_sfen_without_startpos_simple(self, sfen: str) -> None: ...
+    def sfen_to_move(self, sfen: str) -> Move: ...
+    def validate_moves(self, moves: List[str]) -> Optional[Tuple[int, str]]: ...
+    def get_side_to_move(self) -> int: ...
+    def get_ply(self) -> int: ...
+    def get_move_number(self) -> int: ...
+    def get_halfkp_features(self, perspective: int) -> List[int]: ...
+    def get_halfkp_diff(self, m: Move, perspective: int) -> Optional[Tuple[List[int], List[int]]]: ...
+    def generate_moves(self) -> List[Move]: ...
+    def set_try_rule(self, enabled: bool) -> None: ...
+    def is_try(self) -> bool: ...
+    def get_attack_counts(self, color: int) -> List[int]: ...
+    def pinned_pieces(self, color: int) -> List[int]: ...
+    def discovered_check_candidates(self, color: int) -> List[int]: ...
+    def is_in_check(self) -> bool: ...
+    def set_flags(self) -> None: ...
+    def do_move(self, m: Move) -> None: ...
+    def _do_move_with_option(self, m: Move, incremental_update: bool) -> None: ...
+    def undo_move(self) -> None: ...
+    def is_repetition(self) -> Tuple[bool, bool, bool]: ...
+    def get_repetition_state(self) -> int: ...
+    def get_repetition(self) -> int: ...
+    def occurrences(self) -> int: ...
+    def to_svg(self) -> str: ...
+
+class Move:
+    def sfen(self) -> str: ...
+    def csa(self) -> str: ...
+    def __repr__(self) -> str: ...
+
+class AlphaBeta:
+    def __init__(self, tt_size: int = 1048576) -> None: ...
+    def clear(self) -> None: ...
+    def set_node_limit(self, node_limit: int) -> None: ...
+    def set_contempt(self, contempt: int) -> None: ...
+    def get_nodes(self) -> int: ...
+    def get_movegen_stats(self) -> MovegenStats: ...
+    def evaluate(self, position: Position) -> int: ...
+    def search(self, position: Position, depth: int) -> Tuple[Move, int]: ...
+    def search_with_time(self, position: Position, remaining_ms: int, byoyomi_ms: int = 0) -> Tuple[Move, int]: ...
+
+class Anchor:
+    def __init__(self, node_limit: int = 10000) -> None: ...
+    def get_node_limit(self) -> int: ...
+    def choose_move(self, position: Position) -> Move: ...
+
+class MovegenStats:
+    board_moves: int
+    drops: int
+    evasions: int
+    rejections: int
+    def __repr__(self) -> str: ...
+
+def get_movegen_stats() -> MovegenStats: ...
+def reset_movegen_stats() -> None: ...
+def square_file(square: int) -> int: ...
+def square_rank(square: int) -> int: ...
+def make_square(file: int, rank: int) -> int: ...
+def square_to_sfen(square: int) -> str: ...
+def sfen_to_square(sfen: str) -> int: ...
+def generate_moves_batch(positions: List[Position]) -> List[List[Move]]: ...
+def generate_moves_from_sfens(sfens: List[str]) -> List[List[Move]]: ...
+def positions_from_sfens(sfens: List[str]) -> List[Position]: ...
+def run_regression() -> List[str]: ...
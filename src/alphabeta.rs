@@ -0,0 +1,832 @@
+use pyo3::prelude::*;
+
+use movegen_stats::*;
+use position::*;
+use r#move::*;
+use types::*;
+
+pub const INFINITE: i32 = 32000;
+pub const MATE_SCORE: i32 = 30000;
+/// Scores above this value represent a forced mate.
+const MATE_THRESHOLD: i32 = MATE_SCORE - MAX_PLY as i32;
+/// The depth of the iterative deepening when the search is limited by time.
+const MAX_DEPTH: i32 = 64;
+/// The time kept in reserve for the communication overhead.
+const TIME_MARGIN_MS: u64 = 50;
+
+/// Material values indexed by `PieceType`.
+const PIECE_VALUE: [i32; 15] = [0, 0, 550, 500, 650, 750, 100, 0, 0, 0, 0, 600, 850, 950, 600];
+/// Values of hand pieces indexed by the hand index (G, S, B, R, P).
+const HAND_VALUE: [i32; 5] = [600, 550, 700, 800, 120];
+
+/// Piece-square bonuses from the viewpoint of WHITE, who moves toward the rank a.
+/// For BLACK, the square is rotated (`SQUARE_NB - 1 - square`).
+#[rustfmt::skip]
+const PAWN_PSQ: [i32; SQUARE_NB] = [
+     0,  0,  0,  0,  0,
+    30, 30, 30, 30, 30,
+    20, 20, 20, 20, 20,
+    10, 10, 10, 10, 10,
+     0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const GENERAL_PSQ: [i32; SQUARE_NB] = [
+     0,  5, 10,  5,  0,
+    10, 20, 25, 20, 10,
+     5, 15, 20, 15,  5,
+     0, 10, 10, 10,  0,
+     0,  0,  5,  0,  0,
+];
+#[rustfmt::skip]
+const KING_PSQ: [i32; SQUARE_NB] = [
+   -40, -40, -40, -40, -40,
+   -30, -30, -30, -30, -30,
+   -20, -20, -20, -20, -20,
+   -10, -10, -10, -10, -10,
+     0,   0,   0,   0,   0,
+];
+
+const BOUND_EXACT: u8 = 0;
+const BOUND_LOWER: u8 = 1;
+const BOUND_UPPER: u8 = 2;
+
+#[derive(Copy, Clone)]
+struct TTEntry {
+    key: (u64, u64),
+    depth: i32,
+    score: i32,
+    bound: u8,
+    best_move: Move,
+}
+
+const EMPTY_ENTRY: TTEntry =
+    TTEntry { key: (0, 0), depth: -1, score: 0, bound: BOUND_EXACT, best_move: NULL_MOVE };
+
+/// A classical alpha-beta searcher with a handcrafted evaluation.
+///
+/// It is intended as a fixed-strength reference opponent, not as a strong engine.
+#[pyclass(module = "minishogilib", text_signature = "(tt_size=1048576)")]
+pub struct AlphaBeta {
+    tt: std::vec::Vec<TTEntry>,
+    nodes: u64,
+    node_limit: u64,
+    deadline: Option<std::time::Instant>,
+    contempt: i32,
+    root_color: Color,
+    /// `(contempt, root_color)` of the searches which stored the entries of the transposition table.
+    tt_draw_setting: (i32, Color),
+    stopped: bool,
+    movegen_stats: MovegenStats,
+    evaluator: fn(&Position) -> i32,
+    /// Move lists indexed by ply, which are reused to avoid allocations during the search.
+    move_buffers: std::vec::Vec<std::vec::Vec<Move>>,
+}
+
+#[pymethods]
+impl AlphaBeta {
+    /// Arguments:
+    /// * `tt_size`: The number of entries of the transposition table.
+    #[new]
+    #[pyo3(signature = (tt_size = 1 << 20))]
+    pub fn new(tt_size: usize) -> AlphaBeta {
+        AlphaBeta::with_evaluator(tt_size, evaluate)
+    }
+
+    /// Clear the transposition table.
+    pub fn clear(&mut self) {
+        for entry in self.tt.iter_mut() {
+            *entry = EMPTY_ENTRY;
+        }
+    }
+
+    /// Set the maximum number of nodes searched by one `search` call (0 means unlimited).
+    ///
+    /// The first iteration of the iterative deepening is always completed regardless of the limit.
+    #[pyo3(text_signature = "($self, node_limit)")]
+    pub fn set_node_limit(&mut self, node_limit: u64) {
+        self.node_limit = node_limit;
+    }
+
+    /// Set the contempt for draws by repetition and by `MAX_PLY`.
+    ///
+    /// A draw is scored `-contempt` for the side to move at the root and `contempt` for the opponent,
+    /// so a positive contempt avoids draws and a negative one seeks them.
+    /// The transposition table is cleared by the next search if it changes the scores of draws.
+    #[pyo3(text_signature = "($self, contempt)")]
+    pub fn set_contempt(&mut self, contempt: i32) {
+        self.contempt = contempt;
+    }
+
+    /// Return the number of nodes searched by the last `search` call.
+    pub fn get_nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Return the move generation counters of the last `search` call.
+    ///
+    /// All counts are zero unless the library is built with the `movegen-stats` feature.
+    pub fn get_movegen_stats(&self) -> MovegenStats {
+        self.movegen_stats
+    }
+
+    /// Evaluate the position statically from the viewpoint of the side to move.
+    #[pyo3(text_signature = "($self, position)")]
+    pub fn evaluate(&self, position: &Position) -> i32 {
+        (self.evaluator)(position)
+    }
+
+    /// Search the position by iterative deepening.
+    ///
+    /// The GIL is released during the search.
+    ///
+    /// Returns:
+    /// * `(best_move, score)`: The score is from the viewpoint of the side to move.
+    ///                         If there is no legal move, the null move (resign) is returned.
+    #[pyo3(name = "search", text_signature = "($self, position, depth)")]
+    fn py_search(
+        &mut self,
+        py: Python,
+        position: &Position,
+        depth: i32,
+    ) -> Result<(Move, i32), PositionError> {
+        position.check_position_is_set()?;
+
+        Ok(py.allow_threads(|| self.search(position, depth)))
+    }
+
+    /// Search the position by iterative deepening within the time allocated from the clock.
+    ///
+    /// Use `remaining_ms=0` for a fixed time per move. The GIL is released during the search.
+    ///
+    /// Arguments:
+    /// * `remaining_ms`: The remaining time on the clock in milliseconds.
+    /// * `byoyomi_ms`: The time per move after the clock runs out in milliseconds.
+    #[pyo3(
+        name = "search_with_time",
+        signature = (position, remaining_ms, byoyomi_ms = 0),
+        text_signature = "($self, position, remaining_ms, byoyomi_ms=0)"
+    )]
+    fn py_search_with_time(
+        &mut self,
+        py: Python,
+        position: &Position,
+        remaining_ms: u64,
+        byoyomi_ms: u64,
+    ) -> Result<(Move, i32), PositionError> {
+        position.check_position_is_set()?;
+
+        Ok(py.allow_threads(|| self.search_with_time(position, remaining_ms, byoyomi_ms)))
+    }
+}
+
+impl AlphaBeta {
+    /// Create a searcher which evaluates leaf positions by `evaluator` instead of `evaluate`.
+    ///
+    /// `evaluator` must return the score from the viewpoint of the side to move.
+    pub fn with_evaluator(tt_size: usize, evaluator: fn(&Position) -> i32) -> AlphaBeta {
+        AlphaBeta {
+            tt: vec![EMPTY_ENTRY; std::cmp::max(tt_size, 1)],
+            nodes: 0,
+            node_limit: 0,
+            deadline: None,
+            contempt: 0,
+            root_color: Color::WHITE,
+            tt_draw_setting: (0, Color::WHITE),
+            stopped: false,
+            movegen_stats: MovegenStats::default(),
+            evaluator,
+            move_buffers: std::vec::Vec::new(),
+        }
+    }
+
+    /// Search the position by iterative deepening and return `(best_move, score)`.
+    pub fn search(&mut self, position: &Position, depth: i32) -> (Move, i32) {
+        self.iterative_deepening(position, depth, None)
+    }
+
+    /// Search the position within the time allocated from the clock and return `(best_move, score)`.
+    pub fn search_with_time(
+        &mut self,
+        position: &Position,
+        remaining_ms: u64,
+        byoyomi_ms: u64,
+    ) -> (Move, i32) {
+        let budget = allocate_time(remaining_ms, byoyomi_ms);
+
+        self.iterative_deepening(position, MAX_DEPTH, Some(budget))
+    }
+
+    fn iterative_deepening(
+        &mut self,
+        position: &Position,
+        depth: i32,
+        budget: Option<std::time::Duration>,
+    ) -> (Move, i32) {
+        let mut position = position.clone();
+
+        let start = std::time::Instant::now();
+
+        self.nodes = 0;
+        self.stopped = false;
+        self.deadline = budget.map(|budget| start + budget);
+        self.root_color = position.side_to_move;
+
+        // Draw scores in the transposition table depend on the contempt and the color at the root.
+        let draw_setting = (self.contempt, self.root_color);
+        if draw_setting != self.tt_draw_setting && (self.contempt != 0 || self.tt_draw_setting.0 != 0) {
+            self.clear();
+        }
+        self.tt_draw_setting = draw_setting;
+
+        let base_stats = get_movegen_stats();
+        let root_move_num = position.generate_moves().len();
+
+        let mut best = (NULL_MOVE, -MATE_SCORE);
+
+        for d in 1..std::cmp::max(depth, 1) + 1 {
+            // The first iteration is never stopped so that a legal move is always found.
+            let node_limit = self.node_limit;
+            let deadline = self.deadline;
+            if d == 1 {
+                self.node_limit = 0;
+                self.deadline = None;
+            }
+
+            let result = self.search_root(&mut position, d);
+            self.node_limit = node_limit;
+            self.deadline = deadline;
+
+            if self.stopped {
+                break;
+            }
+
+            best = result;
+
+            if best.1.abs() >= MATE_THRESHOLD {
+                break;
+            }
+
+            if let Some(budget) = budget {
+                // The best move cannot change if it is the only legal move.
+                if root_move_num <= 1 {
+                    break;
+                }
+
+                // The next iteration takes longer than all the previous ones, so it would not be completed.
+                if start.elapsed() * 2 > budget {
+                    break;
+                }
+            }
+        }
+
+        self.deadline = None;
+        self.movegen_stats = get_movegen_stats().since(&base_stats);
+
+        best
+    }
+
+    fn search_root(&mut self, position: &mut Position, depth: i32) -> (Move, i32) {
+        let mut alpha = -INFINITE;
+        let beta = INFINITE;
+
+        let mut moves = self.take_move_buffer(0);
+        self.ordered_moves(position, false, &mut moves);
+        let mut best = (NULL_MOVE, -MATE_SCORE);
+
+        // Search the best move of the previous iteration first.
+        if let Some(index) = moves.iter().position(|m| *m == self.probe(position).best_move) {
+            let m = moves.remove(index);
+            moves.insert(0, m);
+        }
+
+        for m in &moves {
+            position.do_move(m);
+
+            if position.is_pawn_drop_mate() {
+                position.undo_move();
+                continue;
+            }
+
+            let score = -self.alpha_beta(position, depth - 1, -beta, -alpha, 1);
+            position.undo_move();
+
+            if self.stopped {
+                break;
+            }
+
+            if score > best.1 || best.0.is_null_move() {
+                best = (*m, score);
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        self.move_buffers[0] = moves;
+
+        if !self.stopped && !best.0.is_null_move() {
+            self.store(position, depth, best.1, BOUND_EXACT, best.0, 0);
+        }
+
+        best
+    }
+
+    fn alpha_beta(
+        &mut self,
+        position: &mut Position,
+        depth: i32,
+        mut alpha: i32,
+        beta: i32,
+        ply: i32,
+    ) -> i32 {
+        if let Some(score) = self.terminal_score(position, ply) {
+            return score;
+        }
+
+        if depth <= 0 {
+            return self.quiescence(position, alpha, beta, ply);
+        }
+
+        if !self.count_node() {
+            return 0;
+        }
+
+        let entry = self.probe(position);
+        if entry.key == position.get_hash() && entry.depth >= depth {
+            let score = score_from_tt(entry.score, ply);
+
+            if entry.bound == BOUND_EXACT
+                || (entry.bound == BOUND_LOWER && score >= beta)
+                || (entry.bound == BOUND_UPPER && score <= alpha)
+            {
+                return score;
+            }
+        }
+
+        let mut moves = self.take_move_buffer(ply as usize);
+        self.ordered_moves(position, false, &mut moves);
+
+        let original_alpha = alpha;
+        let mut best_score = -MATE_SCORE + ply;
+        let mut best_move = NULL_MOVE;
+
+        for m in &moves {
+            position.do_move(m);
+
+            if position.is_pawn_drop_mate() {
+                position.undo_move();
+                continue;
+            }
+
+            let score = -self.alpha_beta(position, depth - 1, -beta, -alpha, ply + 1);
+            position.undo_move();
+
+            if self.stopped {
+                break;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = *m;
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        self.move_buffers[ply as usize] = moves;
+
+        if self.stopped {
+            return 0;
+        }
+
+        let bound = if best_score >= beta {
+            BOUND_LOWER
+        } else if best_score > original_alpha {
+            BOUND_EXACT
+        } else {
+            BOUND_UPPER
+        };
+        self.store(position, depth, best_score, bound, best_move, ply);
+
+        best_score
+    }
+
+    fn quiescence(&mut self, position: &mut Position, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        if !self.count_node() {
+            return 0;
+        }
+
+        if position.is_try() {
+            return -MATE_SCORE + ply;
+        }
+
+        let in_check = position.is_in_check();
+
+        let mut best_score = if in_check { -MATE_SCORE + ply } else { (self.evaluator)(position) };
+
+        if best_score >= beta {
+            return best_score;
+        }
+        if best_score > alpha {
+            alpha = best_score;
+        }
+
+        // Evasions are searched fully, otherwise only captures are searched.
+        let mut moves = self.take_move_buffer(ply as usize);
+        self.ordered_moves(position, !in_check, &mut moves);
+
+        for m in &moves {
+            position.do_move(m);
+
+            if position.is_pawn_drop_mate() {
+                position.undo_move();
+                continue;
+            }
+
+            let score = if position.ply as usize >= MAX_PLY {
+                self.draw_score(position.side_to_move.get_op_color())
+            } else {
+                -self.quiescence(position, -beta, -alpha, ply + 1)
+            };
+            position.undo_move();
+
+            if self.stopped {
+                break;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        self.move_buffers[ply as usize] = moves;
+
+        if self.stopped {
+            return 0;
+        }
+
+        best_score
+    }
+
+    /// Take the move buffer of `ply` out of `move_buffers`. It has to be put back after use.
+    fn take_move_buffer(&mut self, ply: usize) -> std::vec::Vec<Move> {
+        if self.move_buffers.len() <= ply {
+            self.move_buffers.resize(ply + 1, std::vec::Vec::new());
+        }
+
+        std::mem::take(&mut self.move_buffers[ply])
+    }
+
+    /// Return the score if the position is decided by the try rule, the repetition rule or the ply limit.
+    fn terminal_score(&self, position: &Position, ply: i32) -> Option<i32> {
+        if position.is_try() {
+            return Some(-MATE_SCORE + ply);
+        }
+
+        match position.get_repetition_state() {
+            Repetition::DRAW => return Some(self.draw_score(position.side_to_move)),
+            Repetition::WIN => return Some(MATE_SCORE - ply),
+            Repetition::LOSE => return Some(-MATE_SCORE + ply),
+            _ => {}
+        }
+
+        if position.ply as usize >= MAX_PLY {
+            return Some(self.draw_score(position.side_to_move));
+        }
+
+        None
+    }
+
+    /// Return the score of a draw from the viewpoint of `color`.
+    fn draw_score(&self, color: Color) -> i32 {
+        if color == self.root_color {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    /// Count a node, and return false if the node limit is exceeded or the time is up.
+    fn count_node(&mut self) -> bool {
+        self.nodes += 1;
+
+        if self.node_limit > 0 && self.nodes > self.node_limit {
+            self.stopped = true;
+        }
+
+        // Reading the clock is not free, so it is checked only once per 1024 nodes.
+        if self.nodes & 1023 == 0 {
+            if let Some(deadline) = self.deadline {
+                if std::time::Instant::now() >= deadline {
+                    self.stopped = true;
+                }
+            }
+        }
+
+        !self.stopped
+    }
+
+    /// Generate legal moves into `moves` ordered by the TT move, MVV-LVA, and promotions.
+    fn ordered_moves(&self, position: &Position, capture_only: bool, moves: &mut std::vec::Vec<Move>) {
+        position.generate_moves_into(moves);
+
+        if capture_only {
+            moves.retain(|m| m.get_capture_piece() != Piece::NO_PIECE);
+        }
+
+        let entry = self.probe(position);
+        let tt_move = if entry.key == position.get_hash() { entry.best_move } else { NULL_MOVE };
+
+        // `generate_moves` reorders moves by `swap_remove`, so ties are broken by their
+        // encoding to keep the search deterministic.
+        moves.sort_unstable_by_key(|m| {
+            if *m == tt_move {
+                return (-1_000_000, m._data);
+            }
+
+            let mut score = 0;
+
+            if m.get_capture_piece() != Piece::NO_PIECE {
+                score += 10 * PIECE_VALUE[m.get_capture_piece().get_piece_type().as_usize()]
+                    - PIECE_VALUE[m.get_piece().get_piece_type().as_usize()];
+            }
+
+            if m.is_promotion() {
+                score += PIECE_VALUE[m.get_piece().get_piece_type().get_promoted().as_usize()]
+                    - PIECE_VALUE[m.get_piece().get_piece_type().as_usize()];
+            }
+
+            (-score, m._data)
+        });
+    }
+
+    fn probe(&self, position: &Position) -> TTEntry {
+        self.tt[tt_index(position.get_hash(), self.tt.len())]
+    }
+
+    fn store(
+        &mut self,
+        position: &Position,
+        depth: i32,
+        score: i32,
+        bound: u8,
+        best_move: Move,
+        ply: i32,
+    ) {
+        let key = position.get_hash();
+        let index = tt_index(key, self.tt.len());
+
+        if self.tt[index].key == key && self.tt[index].depth > depth {
+            return;
+        }
+
+        self.tt[index] = TTEntry { key, depth, score: score_to_tt(score, ply), bound, best_move };
+    }
+}
+
+/// Allocate the time for one move from the remaining time on the clock and the byoyomi.
+pub fn allocate_time(remaining_ms: u64, byoyomi_ms: u64) -> std::time::Duration {
+    let time_ms = std::cmp::min(remaining_ms / 20 + byoyomi_ms, remaining_ms + byoyomi_ms);
+
+    std::time::Duration::from_millis(std::cmp::max(time_ms.saturating_sub(TIME_MARGIN_MS), 1))
+}
+
+/// Evaluate the position by material and piece-square tables from the viewpoint of the side to move.
+pub fn evaluate(position: &Position) -> i32 {
+    let mut score = [0; 2];
+
+    for square in 0..SQUARE_NB {
+        let piece = position.board[square];
+
+        if piece == Piece::NO_PIECE {
+            continue;
+        }
+
+        let color = piece.get_color();
+        let relative_square = if color == Color::WHITE { square } else { SQUARE_NB - 1 - square };
+        let piece_type = piece.get_piece_type();
+
+        score[color.as_usize()] += PIECE_VALUE[piece_type.as_usize()];
+        score[color.as_usize()] += match piece_type {
+            PieceType::PAWN => PAWN_PSQ[relative_square],
+            PieceType::KING => KING_PSQ[relative_square],
+            PieceType::BISHOP | PieceType::ROOK | PieceType::BISHOP_X | PieceType::ROOK_X => 0,
+            _ => GENERAL_PSQ[relative_square],
+        };
+    }
+
+    for (color, hand) in position.hand.iter().enumerate() {
+        for (index, count) in hand.iter().enumerate() {
+            score[color] += HAND_VALUE[index] * (*count as i32);
+        }
+    }
+
+    let score = score[Color::WHITE.as_usize()] - score[Color::BLACK.as_usize()];
+
+    if position.side_to_move == Color::WHITE {
+        score
+    } else {
+        -score
+    }
+}
+
+fn tt_index(key: (u64, u64), size: usize) -> usize {
+    ((key.0 ^ key.1) % size as u64) as usize
+}
+
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score + ply
+    } else if score <= -MATE_THRESHOLD {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score - ply
+    } else if score <= -MATE_THRESHOLD {
+        score + ply
+    } else {
+        score
+    }
+}
+
+#[test]
+fn evaluate_start_position_test() {
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    // The initial position is point symmetric.
+    assert_eq!(evaluate(&position), 0);
+}
+
+#[test]
+fn mate_in_one_test() {
+    let mut position = Position::empty_board();
+    position.set_sfen("4k/5/4G/5/K4 b G 1").unwrap();
+
+    let mut searcher = AlphaBeta::new(1 << 16);
+    let (m, score) = searcher.search(&position, 3);
+
+    assert_eq!(score, MATE_SCORE - 1);
+
+    position.do_move(&m);
+    assert!(position.is_in_check());
+    assert_eq!(position.generate_moves().len(), 0);
+}
+
+#[test]
+fn mate_problem_without_attacker_king_test() {
+    let mut position = Position::empty_board();
+    position.set_sfen("4k/5/4P/5/5 b G 1").unwrap();
+
+    let mut searcher = AlphaBeta::new(1 << 16);
+    let (m, score) = searcher.search(&position, 3);
+
+    assert_eq!(m.sfen(), "G*1b");
+    assert_eq!(score, MATE_SCORE - 1);
+}
+
+#[test]
+fn pawn_drop_mate_is_not_chosen_test() {
+    let mut position = Position::empty_board();
+    // P*1b would be a checkmate, but it is illegal.
+    position.set_sfen("4k/5/3G1/5/K4 b P 1").unwrap();
+
+    let mut searcher = AlphaBeta::new(1 << 16);
+    let (m, score) = searcher.search(&position, 1);
+
+    assert!(m.sfen() != "P*1b");
+    assert!(score < MATE_THRESHOLD);
+}
+
+#[test]
+fn node_limit_test() {
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    let mut searcher = AlphaBeta::new(1 << 16);
+    searcher.set_node_limit(1000);
+    let (m, _) = searcher.search(&position, 64);
+
+    assert!(position.generate_moves().contains(&m));
+    assert!(searcher.get_nodes() <= 1001);
+
+    // The first iteration is never stopped, so a legal move is returned even with a tiny limit.
+    searcher.set_node_limit(1);
+    let (m, _) = searcher.search(&position, 64);
+
+    assert!(position.generate_moves().contains(&m));
+}
+
+#[test]
+fn try_rule_test() {
+    let mut position = Position::empty_board();
+    position.set_try_rule(true);
+    position.set_sfen("4k/K4/5/5/5 b - 1").unwrap();
+
+    let mut searcher = AlphaBeta::new(1 << 16);
+    let (m, score) = searcher.search(&position, 3);
+
+    assert!(m.get_to() < 5);
+    assert_eq!(score, MATE_SCORE - 1);
+}
+
+#[test]
+fn search_with_time_test() {
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    let mut searcher = AlphaBeta::new(1 << 16);
+
+    let (m, _) = searcher.search_with_time(&position, 0, 200);
+
+    assert!(position.generate_moves().contains(&m));
+    assert_eq!(searcher.deadline, None);
+
+    // 期限を過ぎていれば次に時刻を確認したノードで打ち切る
+    let mut root = position.clone();
+    searcher.nodes = 0;
+    searcher.stopped = false;
+    searcher.deadline = Some(std::time::Instant::now());
+    searcher.search_root(&mut root, 8);
+
+    assert!(searcher.stopped);
+    assert!(searcher.get_nodes() < 2048);
+
+    // 合法手が 1 つしかなければ 1 回目の反復で打ち切る
+    position.set_sfen("k4/5/2B2/5/R3K w - 1").unwrap();
+    let (m, _) = searcher.search_with_time(&position, 600000, 0);
+
+    assert_eq!(m.sfen(), "5a4a");
+    assert!(searcher.get_nodes() < 1000);
+
+    assert_eq!(allocate_time(0, 1000), std::time::Duration::from_millis(950));
+    assert_eq!(allocate_time(60000, 0), std::time::Duration::from_millis(2950));
+    assert_eq!(allocate_time(0, 0), std::time::Duration::from_millis(1));
+}
+
+#[test]
+fn contempt_test() {
+    let mut position = Position::empty_board();
+
+    // 後手は 2b1a で千日手にできる
+    static SFEN: &str = "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e";
+    position.set_sfen(SFEN).unwrap();
+
+    let mut searcher = AlphaBeta::new(1 << 16);
+
+    searcher.set_contempt(-1000);
+    let (m, score) = searcher.search(&position, 1);
+
+    assert_eq!(m.sfen(), "2b1a");
+    assert_eq!(score, 1000);
+
+    searcher.clear();
+    searcher.set_contempt(1000);
+    let (m, score) = searcher.search(&position, 1);
+
+    assert!(m.sfen() != "2b1a");
+    assert!(score > -1000);
+}
+
+#[test]
+fn contempt_root_color_test() {
+    // 先手番の局面と、その 1 手後の後手番の局面
+    static SFEN: &str = "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b";
+    static NEXT_SFEN: &str = "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e";
+
+    let mut position = Position::empty_board();
+    let mut next_position = Position::empty_board();
+    position.set_sfen(SFEN).unwrap();
+    next_position.set_sfen(NEXT_SFEN).unwrap();
+
+    let mut searcher = AlphaBeta::new(1 << 16);
+    searcher.set_contempt(1000);
+
+    // 手番の異なる局面を同じ searcher で探索しても、置換表に残った引き分けの評価値は使われない
+    for root in [&next_position, &position, &next_position].iter() {
+        let mut fresh_searcher = AlphaBeta::new(1 << 16);
+        fresh_searcher.set_contempt(1000);
+
+        assert_eq!(searcher.search(root, 2), fresh_searcher.search(root, 2));
+    }
+}
@@ -0,0 +1,137 @@
+use pyo3::prelude::*;
+
+use alphabeta::*;
+use position::*;
+use r#move::*;
+use types::*;
+
+/// Material values indexed by `PieceType`.
+///
+/// These are kept apart from the values of `alphabeta` so that tuning the evaluation of `AlphaBeta` does not change the anchor.
+const PIECE_VALUE: [i32; 15] = [0, 0, 500, 450, 600, 700, 100, 0, 0, 0, 0, 550, 800, 900, 550];
+/// Values of hand pieces indexed by the hand index (G, S, B, R, P).
+const HAND_VALUE: [i32; 5] = [550, 500, 650, 750, 110];
+/// The bonus for each attack of a piece to a square.
+const MOBILITY_WEIGHT: i32 = 8;
+
+/// The number of entries of the transposition table, which is cleared before every move.
+const TT_SIZE: usize = 1 << 16;
+/// The maximum depth of the iterative deepening. The search is usually stopped by the node limit before it.
+const MAX_DEPTH: i32 = 64;
+
+/// A fixed-strength player which always chooses the same move in the same position.
+///
+/// It searches each position from scratch by `AlphaBeta` with a fixed node limit and a small material + mobility evaluation,
+/// so that it can be used as an Elo anchor shared by different training runs.
+///
+/// Only the evaluation is its own: the search, the move ordering, the transposition table and the quiescence search
+/// are those of `AlphaBeta`, so changing them also changes the moves of the anchor and requires re-rating it.
+#[pyclass(module = "minishogilib", text_signature = "(node_limit=10000)")]
+pub struct Anchor {
+    searcher: AlphaBeta,
+    node_limit: u64,
+}
+
+#[pymethods]
+impl Anchor {
+    /// Arguments:
+    /// * `node_limit`: The number of nodes searched for each move.
+    #[new]
+    #[pyo3(signature = (node_limit = 10000))]
+    pub fn new(node_limit: u64) -> Anchor {
+        let mut searcher = AlphaBeta::with_evaluator(TT_SIZE, evaluate_anchor);
+        searcher.set_node_limit(std::cmp::max(node_limit, 1));
+
+        Anchor { searcher, node_limit }
+    }
+
+    pub fn get_node_limit(&self) -> u64 {
+        self.node_limit
+    }
+
+    /// Choose a move of the position. The GIL is released during the search.
+    ///
+    /// Returns the null move (resign) if there is no legal move.
+    #[pyo3(name = "choose_move", text_signature = "($self, position)")]
+    fn py_choose_move(&mut self, py: Python, position: &Position) -> Result<Move, PositionError> {
+        position.check_position_is_set()?;
+
+        Ok(py.allow_threads(|| self.choose_move(position)))
+    }
+}
+
+impl Anchor {
+    /// Choose a move of the position.
+    pub fn choose_move(&mut self, position: &Position) -> Move {
+        // 探索結果が過去の探索に依存しないように、置換表は毎回クリアする
+        self.searcher.clear();
+
+        self.searcher.search(position, MAX_DEPTH).0
+    }
+}
+
+/// Evaluate the position by material and mobility from the viewpoint of the side to move.
+pub fn evaluate_anchor(position: &Position) -> i32 {
+    let mut score = [0; 2];
+
+    for square in 0..SQUARE_NB {
+        let piece = position.board[square];
+
+        if piece != Piece::NO_PIECE {
+            score[piece.get_color().as_usize()] += PIECE_VALUE[piece.get_piece_type().as_usize()];
+        }
+    }
+
+    for (color, hand) in position.hand.iter().enumerate() {
+        for (index, count) in hand.iter().enumerate() {
+            score[color] += HAND_VALUE[index] * (*count as i32);
+        }
+    }
+
+    for color in [Color::WHITE, Color::BLACK].iter() {
+        let attacks: i32 = position.attack_counts(*color).iter().map(|x| *x as i32).sum();
+
+        score[color.as_usize()] += MOBILITY_WEIGHT * attacks;
+    }
+
+    let score = score[Color::WHITE.as_usize()] - score[Color::BLACK.as_usize()];
+
+    if position.side_to_move == Color::WHITE {
+        score
+    } else {
+        -score
+    }
+}
+
+#[test]
+fn evaluate_anchor_test() {
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    // 初期局面は点対称
+    assert_eq!(evaluate_anchor(&position), 0);
+}
+
+#[test]
+fn anchor_is_deterministic_test() {
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    let mut anchor = Anchor::new(2000);
+    let mut other = Anchor::new(2000);
+
+    for _ in 0..20 {
+        let m = anchor.choose_move(&position);
+
+        if m.is_null_move() {
+            break;
+        }
+
+        // 別のインスタンスでも、同じインスタンスで再度探索しても同じ手を選ぶ
+        assert!(other.choose_move(&position) == m);
+        assert!(anchor.choose_move(&position) == m);
+        assert!(position.generate_moves().contains(&m));
+
+        position.do_move(&m);
+    }
+}
@@ -0,0 +1,142 @@
+use pyo3::prelude::*;
+use rayon::prelude::*;
+
+use position::*;
+use r#move::*;
+
+/// Generate legal moves of each position in parallel.
+///
+/// The positions are checked in order before the generation,
+/// so the error is always the one of the first position which is not set.
+pub fn generate_moves_batch(
+    positions: &[Position],
+) -> Result<std::vec::Vec<std::vec::Vec<Move>>, PositionError> {
+    for (i, position) in positions.iter().enumerate() {
+        position
+            .check_position_is_set()
+            .map_err(|error| PositionError(format!("positions[{}]: {}", i, error)))?;
+    }
+
+    Ok(positions.par_iter().map(|position| position.generate_moves()).collect())
+}
+
+/// Generate legal moves of each sfen represented position in parallel.
+///
+/// Returns the error of the first invalid sfen.
+pub fn generate_moves_from_sfens(
+    sfens: &[String],
+) -> Result<std::vec::Vec<std::vec::Vec<Move>>, PositionError> {
+    let positions = positions_from_sfens(sfens)?;
+
+    Ok(positions.par_iter().map(|position| position.generate_moves()).collect())
+}
+
+/// Set positions by sfens in parallel.
+///
+/// Returns the error of the first invalid sfen.
+pub fn positions_from_sfens(sfens: &[String]) -> Result<std::vec::Vec<Position>, PositionError> {
+    // 並列に集めると途中で失敗した位置が不定になるので、結果を全て集めてから順番に確認する
+    let results: std::vec::Vec<Result<Position, PositionError>> = sfens
+        .par_iter()
+        .enumerate()
+        .map(|(i, sfen)| {
+            let mut position = Position::empty_board();
+            position.set_sfen(sfen).map_err(|error| PositionError(format!("sfens[{}]: {}", i, error)))?;
+
+            Ok(position)
+        })
+        .collect();
+
+    results.into_iter().collect()
+}
+
+/// Generate legal moves of each position in parallel. The GIL is released during the generation.
+///
+/// Raises `ValueError` if any position is not set.
+#[pyfunction]
+#[pyo3(name = "generate_moves_batch", text_signature = "(positions)")]
+pub fn py_generate_moves_batch(
+    py: Python,
+    positions: std::vec::Vec<Position>,
+) -> Result<std::vec::Vec<std::vec::Vec<Move>>, PositionError> {
+    py.allow_threads(|| generate_moves_batch(&positions))
+}
+
+/// Generate legal moves of each sfen represented position in parallel. The GIL is released during the generation.
+///
+/// Raises `ValueError` if any sfen is invalid.
+#[pyfunction]
+#[pyo3(name = "generate_moves_from_sfens", text_signature = "(sfens)")]
+pub fn py_generate_moves_from_sfens(
+    py: Python,
+    sfens: std::vec::Vec<String>,
+) -> Result<std::vec::Vec<std::vec::Vec<Move>>, PositionError> {
+    py.allow_threads(|| generate_moves_from_sfens(&sfens))
+}
+
+/// Set positions by sfens in parallel. The GIL is released while parsing.
+///
+/// Raises `ValueError` if any sfen is invalid.
+#[pyfunction]
+#[pyo3(name = "positions_from_sfens", text_signature = "(sfens)")]
+pub fn py_positions_from_sfens(
+    py: Python,
+    sfens: std::vec::Vec<String>,
+) -> Result<std::vec::Vec<Position>, PositionError> {
+    py.allow_threads(|| positions_from_sfens(&sfens))
+}
+
+#[test]
+fn generate_moves_batch_test() {
+    use rand::seq::SliceRandom;
+
+    let mut rng = rand::thread_rng();
+
+    let mut positions = std::vec::Vec::new();
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    for _ in 0..100 {
+        positions.push(position.clone());
+
+        let moves = position.generate_moves();
+        if moves.is_empty() {
+            break;
+        }
+        position.do_move(moves.choose(&mut rng).unwrap());
+    }
+
+    let sfens: std::vec::Vec<String> = positions.iter().map(|x| x.sfen(true)).collect();
+
+    let parsed_positions = positions_from_sfens(&sfens).unwrap();
+    let batch_moves = generate_moves_batch(&positions).unwrap();
+    let sfen_moves = generate_moves_from_sfens(&sfens).unwrap();
+
+    assert_eq!(batch_moves.len(), positions.len());
+
+    // 順番は入力と一致する
+    for (i, position) in positions.iter().enumerate() {
+        assert!(batch_moves[i] == position.generate_moves());
+        assert!(sfen_moves[i] == position.generate_moves());
+        assert_eq!(parsed_positions[i].sfen(true), sfens[i]);
+        assert_eq!(parsed_positions[i].get_hash(), position.get_hash());
+    }
+
+    positions.push(Position::empty_board());
+    positions.push(Position::empty_board());
+    let error = generate_moves_batch(&positions).err().unwrap();
+    assert!(error.0.starts_with(&format!("positions[{}]", positions.len() - 2)));
+
+    let mut invalid_sfens = sfens.clone();
+    invalid_sfens.insert(1, "invalid".to_string());
+    invalid_sfens.push("invalid".to_string());
+
+    // 不正なsfenが複数ある場合は、常に最初のものが報告される
+    for _ in 0..10 {
+        let error = generate_moves_from_sfens(&invalid_sfens).err().unwrap();
+        assert!(error.0.starts_with("sfens[1]:"));
+
+        let error = positions_from_sfens(&invalid_sfens).err().unwrap();
+        assert!(error.0.starts_with("sfens[1]:"));
+    }
+}
@@ -487,6 +487,72 @@ static ROOK_ATTACK2: Lazy<[[Bitboard; 32]; SQUARE_NB]> = Lazy::new(||{
     return ra;
 });
 
+/// 2つの升の間にある升を保持するbitboard (両端は含まない)
+/// 2つの升が縦横斜めに並んでいない場合は0
+static BETWEEN_BB: Lazy<[[Bitboard; SQUARE_NB]; SQUARE_NB]> = Lazy::new(|| {
+    let mut bb: [[Bitboard; SQUARE_NB]; SQUARE_NB] = [[0; SQUARE_NB]; SQUARE_NB];
+
+    for (i, row) in bb.iter_mut().enumerate() {
+        for (j, between) in row.iter_mut().enumerate() {
+            if let Some((dx, dy)) = direction(i, j) {
+                let mut x = (i % 5) as i8 + dx;
+                let mut y = (i / 5) as i8 + dy;
+
+                while (y * 5 + x) as usize != j {
+                    *between |= 1 << (y * 5 + x);
+
+                    x += dx;
+                    y += dy;
+                }
+            }
+        }
+    }
+
+    bb
+});
+
+/// 2つの升を通る直線上の升を保持するbitboard (両端を含む)
+/// 2つの升が縦横斜めに並んでいない場合は0
+static LINE_BB: Lazy<[[Bitboard; SQUARE_NB]; SQUARE_NB]> = Lazy::new(|| {
+    let mut bb: [[Bitboard; SQUARE_NB]; SQUARE_NB] = [[0; SQUARE_NB]; SQUARE_NB];
+
+    for (i, row) in bb.iter_mut().enumerate() {
+        for (j, line) in row.iter_mut().enumerate() {
+            if let Some((dx, dy)) = direction(i, j) {
+                *line |= 1 << i;
+
+                // iから両方向に盤の端まで伸ばす
+                for sign in [1, -1].iter() {
+                    let mut x = (i % 5) as i8 + dx * sign;
+                    let mut y = (i / 5) as i8 + dy * sign;
+
+                    while (0..5).contains(&x) && (0..5).contains(&y) {
+                        *line |= 1 << (y * 5 + x);
+
+                        x += dx * sign;
+                        y += dy * sign;
+                    }
+                }
+            }
+        }
+    }
+
+    bb
+});
+
+/// 升fromから升toへの単位方向 (dx, dy) を返す
+/// 縦横斜めに並んでいない場合 (同じ升を含む) はNone
+fn direction(from: usize, to: usize) -> Option<(i8, i8)> {
+    let dx = (to % 5) as i8 - (from % 5) as i8;
+    let dy = (to / 5) as i8 - (from / 5) as i8;
+
+    if (dx == 0 && dy == 0) || (dx != 0 && dy != 0 && dx.abs() != dy.abs()) {
+        return None;
+    }
+
+    Some((dx.signum(), dy.signum()))
+}
+
 pub fn adjacent_attack(square: usize, piece: Piece) -> Bitboard {
     ADJACENT_ATTACK[square][piece.as_usize()]
 }
@@ -501,11 +567,33 @@ pub fn rook_attack(square: usize, player_bb: Bitboard) -> Bitboard {
         | ROOK_ATTACK2[square][player_bb.pext(ROOK_MASK2[square]) as usize]
 }
 
+/// 升aと升bの間にある升 (両端は含まない)
+pub fn between_bb(a: usize, b: usize) -> Bitboard {
+    BETWEEN_BB[a][b]
+}
+
+/// 升aと升bを通る直線上の升 (両端を含む)
+pub fn line_bb(a: usize, b: usize) -> Bitboard {
+    LINE_BB[a][b]
+}
+
 /// 一番末尾の1の場所を返す
 pub fn get_square(bb: Bitboard) -> usize {
     bb.trailing_zeros() as usize
 }
 
+/// 1の立っている場所を昇順に全て返す
+pub fn get_squares(mut bb: Bitboard) -> std::vec::Vec<usize> {
+    let mut squares = std::vec::Vec::new();
+
+    while bb != 0 {
+        squares.push(get_square(bb));
+        bb &= bb - 1;
+    }
+
+    squares
+}
+
 /// 1の数を返す
 pub fn get_counts(bb: Bitboard) -> u32 {
     bb.popcnt()
@@ -5,9 +5,18 @@ extern crate rayon;
 extern crate serde;
 extern crate once_cell;
 
+pub mod alphabeta;
+pub mod anchor;
+pub mod batch;
 pub mod bitboard;
 pub mod r#move;
+#[cfg(test)]
+pub mod movegen_reference;
+pub mod movegen_stats;
+pub mod nnue;
 pub mod position;
+pub mod regression;
+pub mod square;
 pub mod types;
 pub mod zobrist;
 
@@ -16,9 +25,30 @@ use pyo3::prelude::*;
 #[pymodule]
 fn minishogilib(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("HALFKP_FEATURE_NB", nnue::HALFKP_FEATURE_NB)?;
+    m.add("SQUARE_NB", types::SQUARE_NB)?;
+    m.add("REPETITION_NONE", types::Repetition::NONE.0)?;
+    m.add("REPETITION_DRAW", types::Repetition::DRAW.0)?;
+    m.add("REPETITION_WIN", types::Repetition::WIN.0)?;
+    m.add("REPETITION_LOSE", types::Repetition::LOSE.0)?;
 
     m.add_class::<position::Position>()?;
     m.add_class::<r#move::Move>()?;
+    m.add_class::<alphabeta::AlphaBeta>()?;
+    m.add_class::<anchor::Anchor>()?;
+    m.add_class::<movegen_stats::MovegenStats>()?;
+
+    m.add_function(wrap_pyfunction!(movegen_stats::get_movegen_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(movegen_stats::reset_movegen_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(square::py_square_file, m)?)?;
+    m.add_function(wrap_pyfunction!(square::py_square_rank, m)?)?;
+    m.add_function(wrap_pyfunction!(square::py_make_square, m)?)?;
+    m.add_function(wrap_pyfunction!(square::py_square_to_sfen, m)?)?;
+    m.add_function(wrap_pyfunction!(square::py_sfen_to_square, m)?)?;
+    m.add_function(wrap_pyfunction!(batch::py_generate_moves_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(batch::py_generate_moves_from_sfens, m)?)?;
+    m.add_function(wrap_pyfunction!(batch::py_positions_from_sfens, m)?)?;
+    m.add_function(wrap_pyfunction!(regression::py_run_regression, m)?)?;
 
     Ok(())
 }
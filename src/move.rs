@@ -3,6 +3,10 @@ use once_cell::sync::Lazy;
 
 use types::*;
 
+/// CSA representations of pieces indexed by `PieceType`.
+pub const CSA_PIECE: [&str; 15] =
+    ["--", "OU", "KI", "GI", "KA", "HI", "FU", "--", "--", "--", "--", "NG", "UM", "RY", "TO"];
+
 #[pyclass]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Move {
@@ -51,16 +55,11 @@ impl Move {
             return "%TORYO".to_string();
         }
 
-        let csa_piece = [
-            "--", "OU", "KI", "GI", "KA", "HI", "FU", "--", "--", "--", "--", "NG", "UM", "RY",
-            "TO",
-        ];
-
         if self.is_hand() {
             format!(
                 "00{}{}",
                 square_to_csa(self.get_to()),
-                csa_piece[self.get_piece().get_piece_type().as_usize()]
+                CSA_PIECE[self.get_piece().get_piece_type().as_usize()]
             )
         } else {
             let piece = if self.is_promotion() {
@@ -73,7 +72,7 @@ impl Move {
                 "{}{}{}",
                 square_to_csa(self.get_from()),
                 square_to_csa(self.get_to()),
-                csa_piece[piece.as_usize()]
+                CSA_PIECE[piece.as_usize()]
             )
         }
     }
@@ -0,0 +1,256 @@
+//! A slow but straightforward move generator, which is used to cross-check the bitboard move generator.
+//!
+//! It looks only at `board`, `hand` and `side_to_move`, and follows the pieces square by square
+//! without any precomputed table.
+
+use rand::seq::SliceRandom;
+
+use position::*;
+use r#move::*;
+use types::*;
+
+/// Directions as `(dy, dx)`.
+type Directions = std::vec::Vec<(i8, i8)>;
+
+/// Return `(steps, slides)` of the piece type from the viewpoint of WHITE, who moves toward the rank a.
+fn piece_directions(piece_type: PieceType) -> (Directions, Directions) {
+    let orthogonal = vec![(-1, 0), (0, 1), (1, 0), (0, -1)];
+    let diagonal = vec![(-1, -1), (-1, 1), (1, -1), (1, 1)];
+    let gold = vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0)];
+
+    match piece_type {
+        PieceType::KING => ([&orthogonal[..], &diagonal[..]].concat(), vec![]),
+        PieceType::GOLD | PieceType::SILVER_X | PieceType::PAWN_X => (gold, vec![]),
+        PieceType::SILVER => (vec![(-1, -1), (-1, 0), (-1, 1), (1, -1), (1, 1)], vec![]),
+        PieceType::PAWN => (vec![(-1, 0)], vec![]),
+        PieceType::BISHOP => (vec![], diagonal),
+        PieceType::ROOK => (vec![], orthogonal),
+        PieceType::BISHOP_X => (orthogonal, diagonal),
+        PieceType::ROOK_X => (diagonal, orthogonal),
+        _ => (vec![], vec![]),
+    }
+}
+
+/// Return the squares to which the piece on `square` can move (or which it attacks), including squares of own pieces.
+fn reachable_squares(board: &[Piece; SQUARE_NB], square: usize) -> std::vec::Vec<usize> {
+    let piece = board[square];
+    let (steps, slides) = piece_directions(piece.get_piece_type());
+    let sign = if piece.get_color() == Color::WHITE { 1 } else { -1 };
+
+    let y = (square / 5) as i8;
+    let x = (square % 5) as i8;
+    let on_board = |y: i8, x: i8| (0..5).contains(&y) && (0..5).contains(&x);
+
+    let mut squares = std::vec::Vec::new();
+
+    for (dy, dx) in steps {
+        let (ny, nx) = (y + dy * sign, x + dx * sign);
+
+        if on_board(ny, nx) {
+            squares.push((ny * 5 + nx) as usize);
+        }
+    }
+
+    for (dy, dx) in slides {
+        let (mut ny, mut nx) = (y + dy * sign, x + dx * sign);
+
+        while on_board(ny, nx) {
+            let target = (ny * 5 + nx) as usize;
+            squares.push(target);
+
+            if board[target] != Piece::NO_PIECE {
+                break;
+            }
+
+            ny += dy * sign;
+            nx += dx * sign;
+        }
+    }
+
+    squares
+}
+
+fn is_attacked(board: &[Piece; SQUARE_NB], square: usize, color: Color) -> bool {
+    (0..SQUARE_NB).any(|from| {
+        board[from] != Piece::NO_PIECE
+            && board[from].get_color() == color
+            && reachable_squares(board, from).contains(&square)
+    })
+}
+
+fn is_last_rank(square: usize, color: Color) -> bool {
+    if color == Color::WHITE {
+        square < 5
+    } else {
+        square >= 20
+    }
+}
+
+/// Whether the king of the side to move is not attacked after the move.
+fn is_legal(position: &Position, m: &Move) -> bool {
+    let color = position.side_to_move;
+    let mut board = position.board;
+
+    if m.is_hand() {
+        board[m.get_to()] = m.get_piece();
+    } else {
+        board[m.get_from()] = Piece::NO_PIECE;
+        board[m.get_to()] = if m.is_promotion() { m.get_piece().get_promoted() } else { m.get_piece() };
+    }
+
+    let king = PieceType::KING.get_piece(color);
+
+    match (0..SQUARE_NB).find(|square| board[*square] == king) {
+        Some(king_square) => !is_attacked(&board, king_square, color.get_op_color()),
+        None => true,
+    }
+}
+
+/// Generate legal moves in the same way as `Position::generate_moves`,
+/// i.e. a checkmate by a pawn drop (Utifu-dume) is included.
+pub fn generate_moves(position: &Position) -> std::vec::Vec<Move> {
+    let color = position.side_to_move;
+    let mut moves = std::vec::Vec::new();
+
+    for from in 0..SQUARE_NB {
+        let piece = position.board[from];
+
+        if piece == Piece::NO_PIECE || piece.get_color() != color {
+            continue;
+        }
+
+        for to in reachable_squares(&position.board, from) {
+            let capture_piece = position.board[to];
+
+            if capture_piece != Piece::NO_PIECE && capture_piece.get_color() == color {
+                continue;
+            }
+
+            let can_promote = piece.is_raw()
+                && piece.is_promotable()
+                && (is_last_rank(from, color) || is_last_rank(to, color));
+            let must_promote = piece.get_piece_type() == PieceType::PAWN && is_last_rank(to, color);
+
+            if !must_promote {
+                moves.push(Move::board_move(piece, from, to, false, capture_piece));
+            }
+            if can_promote {
+                moves.push(Move::board_move(piece, from, to, true, capture_piece));
+            }
+        }
+    }
+
+    for (index, piece_type) in HAND_PIECE_TYPE_ALL.iter().enumerate() {
+        if position.hand[color.as_usize()][index] == 0 {
+            continue;
+        }
+
+        let piece = piece_type.get_piece(color);
+
+        for to in 0..SQUARE_NB {
+            if position.board[to] != Piece::NO_PIECE {
+                continue;
+            }
+
+            if *piece_type == PieceType::PAWN {
+                let nifu = (0..5).any(|y| position.board[y * 5 + to % 5] == piece);
+
+                if nifu || is_last_rank(to, color) {
+                    continue;
+                }
+            }
+
+            moves.push(Move::hand_move(piece, to));
+        }
+    }
+
+    moves.retain(|m| is_legal(position, m));
+
+    moves
+}
+
+/// Compare the bitboard move generator with the reference one.
+///
+/// Returns a description of the difference including the sfen of the position if they disagree.
+pub fn find_mismatch(position: &Position) -> Option<String> {
+    let to_sorted_sfens = |moves: std::vec::Vec<Move>| {
+        let mut sfens: std::vec::Vec<String> = moves.iter().map(|m| m.sfen()).collect();
+        sfens.sort();
+        sfens
+    };
+
+    let expected = to_sorted_sfens(generate_moves(position));
+    let actual = to_sorted_sfens(position.generate_moves());
+
+    if expected == actual {
+        return None;
+    }
+
+    let missing: std::vec::Vec<&String> = expected.iter().filter(|m| !actual.contains(m)).collect();
+    let extra: std::vec::Vec<&String> = actual.iter().filter(|m| !expected.contains(m)).collect();
+
+    Some(format!(
+        "sfen: {}\nmissing: {:?}\nextra: {:?}\n(expected {} moves, generated {} moves)",
+        position.sfen(false),
+        missing,
+        extra,
+        expected.len(),
+        actual.len()
+    ))
+}
+
+/// Play `game_num` random games and check the move generator at every position.
+///
+/// Panics with the first mismatching position.
+pub fn fuzz(game_num: usize) {
+    let mut position = Position::empty_board();
+
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..game_num {
+        position.set_start_position();
+
+        while position.ply < MAX_PLY as u16 {
+            if let Some(mismatch) = find_mismatch(&position) {
+                panic!("the move generator disagrees with the reference:\n{}", mismatch);
+            }
+
+            let moves = position.generate_moves();
+
+            if moves.is_empty() {
+                break;
+            }
+
+            // ランダムに局面を進める
+            let random_move = moves.choose(&mut rng).unwrap();
+            position.do_move(random_move);
+        }
+    }
+}
+
+#[test]
+fn movegen_reference_test() {
+    let mut position = Position::empty_board();
+
+    static SFENS: [&str; 4] = [
+        "rbsgk/4p/5/P4/KGSBR b - 1",
+        "r3k/5/5/G4/K2R1 b - 1",
+        "4k/2S2/3G1/5/K4 b P 1",
+        "2k2/5/5/5/2K2 b GSBRPgsbrp 1",
+    ];
+
+    for sfen in SFENS.iter() {
+        position.set_sfen(sfen).unwrap();
+
+        assert_eq!(find_mismatch(&position), None);
+    }
+
+    fuzz(5);
+}
+
+/// Run by `cargo test --release -- --ignored` before landing changes to the move generator.
+#[test]
+#[ignore]
+fn movegen_reference_fuzz_test() {
+    fuzz(100000);
+}
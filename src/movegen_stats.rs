@@ -0,0 +1,94 @@
+use pyo3::prelude::*;
+
+use std::cell::Cell;
+
+/// Counters of generated moves, which are updated only if the `movegen-stats` feature is enabled.
+///
+/// The counters are thread local, so a search running on one thread is not disturbed by others.
+#[pyclass(module = "minishogilib")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MovegenStats {
+    /// The number of moves of pieces on the board, including illegal ones removed later.
+    #[pyo3(get)]
+    pub board_moves: u64,
+    /// The number of moves dropping hand pieces, including illegal ones removed later.
+    #[pyo3(get)]
+    pub drops: u64,
+    /// The number of moves which remain after removing illegal ones while the king is in check.
+    #[pyo3(get)]
+    pub evasions: u64,
+    /// The number of moves removed because they leave the king in check.
+    #[pyo3(get)]
+    pub rejections: u64,
+}
+
+#[pymethods]
+impl MovegenStats {
+    fn __repr__(&self) -> String {
+        format!(
+            "MovegenStats(board_moves={}, drops={}, evasions={}, rejections={})",
+            self.board_moves, self.drops, self.evasions, self.rejections
+        )
+    }
+}
+
+impl MovegenStats {
+    /// Return the counts accumulated since `base`.
+    pub fn since(&self, base: &MovegenStats) -> MovegenStats {
+        MovegenStats {
+            board_moves: self.board_moves - base.board_moves,
+            drops: self.drops - base.drops,
+            evasions: self.evasions - base.evasions,
+            rejections: self.rejections - base.rejections,
+        }
+    }
+}
+
+thread_local! {
+    static STATS: Cell<MovegenStats> = Cell::new(MovegenStats::default());
+}
+
+/// Update the counters of the current thread. This is a no-op without the `movegen-stats` feature.
+#[inline(always)]
+pub fn record<F: FnOnce(&mut MovegenStats)>(f: F) {
+    if cfg!(feature = "movegen-stats") {
+        STATS.with(|stats| {
+            let mut value = stats.get();
+            f(&mut value);
+            stats.set(value);
+        });
+    }
+}
+
+/// Return the counters of the current thread.
+#[pyfunction]
+pub fn get_movegen_stats() -> MovegenStats {
+    STATS.with(|stats| stats.get())
+}
+
+/// Reset the counters of the current thread.
+#[pyfunction]
+pub fn reset_movegen_stats() {
+    STATS.with(|stats| stats.set(MovegenStats::default()));
+}
+
+#[test]
+fn movegen_stats_test() {
+    use position::*;
+
+    reset_movegen_stats();
+
+    let mut position = Position::empty_board();
+    position.set_start_position();
+    let moves = position.generate_moves();
+
+    let stats = get_movegen_stats();
+
+    if cfg!(feature = "movegen-stats") {
+        assert_eq!(stats.board_moves + stats.drops - stats.rejections, moves.len() as u64);
+        assert_eq!(stats.drops, 0);
+        assert_eq!(stats.evasions, 0);
+    } else {
+        assert_eq!(stats, MovegenStats::default());
+    }
+}
@@ -0,0 +1,349 @@
+#[cfg(test)]
+use rand::seq::SliceRandom;
+
+use bitboard::*;
+use position::*;
+use r#move::*;
+use types::*;
+
+/// The number of features of pieces in hand: (friend, enemy) x (G, S, B, R, P) x (1st, 2nd).
+pub const HAND_FEATURE_NB: usize = 2 * 5 * 2;
+/// The number of features of pieces on the board: (friend, enemy) x (9 piece types except king) x squares.
+pub const BOARD_FEATURE_NB: usize = 2 * 9 * SQUARE_NB;
+/// The number of (non-king) piece features.
+pub const PIECE_FEATURE_NB: usize = HAND_FEATURE_NB + BOARD_FEATURE_NB;
+/// The number of HalfKP features, i.e. king squares x piece features.
+pub const HALFKP_FEATURE_NB: usize = SQUARE_NB * PIECE_FEATURE_NB;
+
+/// The number of words of the bit set of piece features.
+const PIECE_WORD_NB: usize = PIECE_FEATURE_NB.div_ceil(64);
+
+/// Features which are (removed, added) by a move.
+pub type FeatureDiff = (std::vec::Vec<usize>, std::vec::Vec<usize>);
+
+/// Rotate the square so that `perspective` always moves toward the rank a.
+fn orient(square: usize, perspective: Color) -> usize {
+    if perspective == Color::WHITE {
+        square
+    } else {
+        SQUARE_NB - 1 - square
+    }
+}
+
+fn board_piece_index(piece_type: PieceType) -> usize {
+    if piece_type.is_promoted() {
+        piece_type.as_usize() - PieceType::SILVER_X.as_usize() + 5
+    } else {
+        piece_type.as_usize() - PieceType::GOLD.as_usize()
+    }
+}
+
+fn board_feature(piece: Piece, square: usize, perspective: Color) -> usize {
+    let relative = if piece.get_color() == perspective { 0 } else { 1 };
+
+    HAND_FEATURE_NB
+        + (relative * 9 + board_piece_index(piece.get_piece_type())) * SQUARE_NB
+        + orient(square, perspective)
+}
+
+/// Arguments:
+/// * `count`: The number of the hand pieces including this one (1 or 2).
+fn hand_feature(color: Color, hand_index: usize, count: u8, perspective: Color) -> usize {
+    let relative = if color == perspective { 0 } else { 1 };
+
+    (relative * 5 + hand_index) * 2 + (count as usize - 1)
+}
+
+fn king_square(position: &Position, perspective: Color) -> usize {
+    orient(
+        get_square(position.piece_bb[PieceType::KING.get_piece(perspective).as_usize()]),
+        perspective,
+    )
+}
+
+/// Return the active piece features (without the king square) from the viewpoint of `perspective`.
+fn piece_features(position: &Position, perspective: Color) -> std::vec::Vec<usize> {
+    let mut features = Vec::new();
+
+    for color in [Color::WHITE, Color::BLACK].iter() {
+        for hand_index in 0..5 {
+            for count in 1..position.hand[color.as_usize()][hand_index] + 1 {
+                features.push(hand_feature(*color, hand_index, count, perspective));
+            }
+        }
+    }
+
+    for square in 0..SQUARE_NB {
+        let piece = position.board[square];
+
+        if piece == Piece::NO_PIECE || piece.get_piece_type() == PieceType::KING {
+            continue;
+        }
+
+        features.push(board_feature(piece, square, perspective));
+    }
+
+    features
+}
+
+/// Call `f(removed, added)` for each piece feature changed by the move `m`, from the viewpoint of `perspective`.
+fn for_each_change<F: FnMut(usize, usize)>(position: &Position, m: &Move, perspective: Color, mut f: F) {
+    let piece = m.get_piece();
+    let color = position.side_to_move;
+
+    if m.is_hand() {
+        let hand_index = m.get_hand_index();
+        let count = position.hand[color.as_usize()][hand_index];
+
+        f(hand_feature(color, hand_index, count, perspective), board_feature(piece, m.get_to(), perspective));
+
+        return;
+    }
+
+    if piece.get_piece_type() != PieceType::KING {
+        let moved_piece = if m.is_promotion() { piece.get_promoted() } else { piece };
+
+        f(board_feature(piece, m.get_from(), perspective), board_feature(moved_piece, m.get_to(), perspective));
+    }
+
+    let capture_piece = m.get_capture_piece();
+
+    if capture_piece != Piece::NO_PIECE {
+        let hand_index = capture_piece.get_piece_type().get_raw().as_usize() - 2;
+        let count = position.hand[color.as_usize()][hand_index] + 1;
+
+        f(board_feature(capture_piece, m.get_to(), perspective), hand_feature(color, hand_index, count, perspective));
+    }
+}
+
+/// Return the sorted indices of active HalfKP features from the viewpoint of `perspective`.
+///
+/// The king of `perspective` must be on the board.
+pub fn halfkp_features(position: &Position, perspective: Color) -> std::vec::Vec<usize> {
+    let offset = king_square(position, perspective) * PIECE_FEATURE_NB;
+
+    let mut features: std::vec::Vec<usize> =
+        piece_features(position, perspective).iter().map(|feature| offset + feature).collect();
+
+    features.sort_unstable();
+
+    features
+}
+
+/// The active piece features of both perspectives, which `Position` updates incrementally on `do_move` and `undo_move`.
+///
+/// A HalfKP feature is `king square * PIECE_FEATURE_NB + piece feature`, and the piece features do not depend on
+/// the king square, so a king move never requires a refresh.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Accumulator {
+    /// The bit set of piece features indexed by the perspective.
+    pieces: [[u64; PIECE_WORD_NB]; 2],
+}
+
+impl Accumulator {
+    /// Compute the features of the position from scratch.
+    pub fn new(position: &Position) -> Accumulator {
+        let mut accumulator = Accumulator::default();
+
+        for perspective in [Color::WHITE, Color::BLACK].iter() {
+            for feature in piece_features(position, *perspective) {
+                accumulator.set(*perspective, feature);
+            }
+        }
+
+        accumulator
+    }
+
+    /// Return the features after doing the move `m` in `position`.
+    pub fn moved(&self, position: &Position, m: &Move) -> Accumulator {
+        let mut accumulator = *self;
+
+        for perspective in [Color::WHITE, Color::BLACK].iter() {
+            for_each_change(position, m, *perspective, |removed, added| {
+                accumulator.clear(*perspective, removed);
+                accumulator.set(*perspective, added);
+            });
+        }
+
+        accumulator
+    }
+
+    /// Return the features before the move `m`, where `position` is the one before the move.
+    pub fn unmoved(&self, position: &Position, m: &Move) -> Accumulator {
+        let mut accumulator = *self;
+
+        for perspective in [Color::WHITE, Color::BLACK].iter() {
+            for_each_change(position, m, *perspective, |removed, added| {
+                accumulator.clear(*perspective, added);
+                accumulator.set(*perspective, removed);
+            });
+        }
+
+        accumulator
+    }
+
+    /// Return the sorted indices of active HalfKP features from the viewpoint of `perspective`,
+    /// whose king must be on the board.
+    pub fn features(&self, position: &Position, perspective: Color) -> std::vec::Vec<usize> {
+        let offset = king_square(position, perspective) * PIECE_FEATURE_NB;
+
+        let mut features = Vec::new();
+
+        for (i, word) in self.pieces[perspective.as_usize()].iter().enumerate() {
+            let mut bits = *word;
+
+            while bits != 0 {
+                features.push(offset + i * 64 + bits.trailing_zeros() as usize);
+                bits &= bits - 1;
+            }
+        }
+
+        features
+    }
+
+    fn set(&mut self, perspective: Color, feature: usize) {
+        self.pieces[perspective.as_usize()][feature / 64] |= 1 << (feature % 64);
+    }
+
+    fn clear(&mut self, perspective: Color, feature: usize) {
+        self.pieces[perspective.as_usize()][feature / 64] &= !(1 << (feature % 64));
+    }
+}
+
+/// Return the features which are removed and added by doing the move `m`, from the viewpoint of `perspective`.
+///
+/// Returns `None` if the king of `perspective` moves, when all features have to be refreshed.
+/// The king of `perspective` must be on the board.
+pub fn halfkp_feature_diff(
+    position: &Position,
+    m: &Move,
+    perspective: Color,
+) -> Option<FeatureDiff> {
+    if !m.is_hand() && m.get_piece().get_piece_type() == PieceType::KING && position.side_to_move == perspective {
+        return None;
+    }
+
+    let offset = king_square(position, perspective) * PIECE_FEATURE_NB;
+
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+
+    for_each_change(position, m, perspective, |removed_feature, added_feature| {
+        removed.push(offset + removed_feature);
+        added.push(offset + added_feature);
+    });
+
+    Some((removed, added))
+}
+
+#[test]
+fn halfkp_features_test() {
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    let white_features = halfkp_features(&position, Color::WHITE);
+    let black_features = halfkp_features(&position, Color::BLACK);
+
+    // 10 pieces except kings are on the board.
+    assert_eq!(white_features.len(), 10);
+
+    // The initial position is point symmetric.
+    assert_eq!(white_features, black_features);
+
+    for feature in &white_features {
+        assert!(*feature < HALFKP_FEATURE_NB);
+    }
+
+    assert_eq!(position.get_halfkp_features(0).unwrap(), white_features);
+    assert!(position.get_halfkp_features(2).is_err());
+
+    let m = position.generate_moves()[0];
+    assert!(position.get_halfkp_diff(&m, 1).unwrap().is_some());
+    assert!(position.get_halfkp_diff(&m, 2).is_err());
+}
+
+/// Check the feature differences and the accumulator for every legal move of the position.
+#[cfg(test)]
+fn check_feature_diff(position: &Position) {
+    for m in &position.generate_moves() {
+        let mut next_position = position.clone();
+        next_position.do_move(m);
+
+        for perspective in [Color::WHITE, Color::BLACK].iter() {
+            let expected = halfkp_features(&next_position, *perspective);
+
+            // 差分更新の結果が全計算と一致することを確認する
+            if let Some((removed, added)) = halfkp_feature_diff(position, m, *perspective) {
+                let mut features = halfkp_features(position, *perspective);
+                for feature in &removed {
+                    let index = features.iter().position(|x| x == feature).unwrap();
+                    features.remove(index);
+                }
+                features.extend(added);
+                features.sort_unstable();
+
+                assert_eq!(features, expected);
+            } else {
+                assert!(m.get_piece().get_piece_type() == PieceType::KING);
+            }
+
+            assert_eq!(next_position.accumulator.features(&next_position, *perspective), expected);
+        }
+
+        next_position.undo_move();
+        assert_eq!(next_position.accumulator, position.accumulator);
+    }
+}
+
+#[cfg(test)]
+fn check_random_games(game_num: usize) {
+    let mut position = Position::empty_board();
+
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..game_num {
+        position.set_start_position();
+
+        while position.ply < MAX_PLY as u16 {
+            check_feature_diff(&position);
+
+            let moves = position.generate_moves();
+
+            if moves.is_empty() {
+                break;
+            }
+
+            // ランダムに局面を進める
+            let random_move = moves.choose(&mut rng).unwrap();
+            position.do_move(random_move);
+        }
+    }
+}
+
+#[test]
+fn halfkp_feature_diff_test() {
+    let mut position = Position::empty_board();
+
+    static SFENS: [&str; 4] = [
+        "rbsgk/4p/5/P4/KGSBR b - 1",
+        "2k2/5/5/5/2K2 b GSBRPgsbrp 1",
+        "rb1gk/1s2R/5/P1B2/KGS2 w P 1",
+        "4k/4P/5/5/K4 w - 1",
+    ];
+
+    for sfen in SFENS.iter() {
+        position.set_sfen(sfen).unwrap();
+
+        assert_eq!(position.accumulator, Accumulator::new(&position));
+        check_feature_diff(&position);
+    }
+
+    check_random_games(1);
+}
+
+/// Run by `cargo test --release -- --ignored` before landing changes to the features.
+#[test]
+#[ignore]
+fn halfkp_feature_diff_sweep_test() {
+    check_random_games(1000);
+}
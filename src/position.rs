@@ -1,14 +1,55 @@
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 #[cfg(test)]
 use rand::seq::SliceRandom;
+use std::sync::Arc;
 
 use bitboard::*;
 use r#move::*;
 use types::*;
 
+/// An error caused by an invalid sfen or move, which is raised as `ValueError` in Python.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionError(pub String);
+
+impl std::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl std::convert::From<PositionError> for PyErr {
+    fn from(error: PositionError) -> PyErr {
+        PyValueError::new_err(error.0)
+    }
+}
+
+/// The sfen of the initial position, which is also set by the keyword `startpos`.
+pub static START_POSITION_SFEN: &str = "rbsgk/4p/5/P4/KGSBR b - 1";
+
+/// The history of a game from the position set by sfen.
+#[derive(Clone)]
+pub struct History {
+    /// Move history, whose length is `ply`.
+    pub kif: std::vec::Vec<Move>,
+    /// The hash value of positions (including history positions), whose length is `ply + 1`.
+    pub hash: std::vec::Vec<(u64, u64)>,
+    /// The bitboard of pieces which are checking by neighbors.
+    pub adjacent_check_bb: std::vec::Vec<Bitboard>,
+    /// The bitboard of pieces which are checking not by neighbors.
+    pub long_check_bb: std::vec::Vec<Bitboard>,
+    /// The number of sequential check (including history positions).
+    pub sequent_check_count: std::vec::Vec<[u8; 2]>,
+}
+
 /// A position is represented here.
+///
+/// Historical variables are kept in a `History` shared by copies of the position,
+/// so that cloning a position does not copy the moves played so far.
 #[pyclass(module = "minishogilib")]
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Position {
     /// Which player to move.
     pub side_to_move: Color,
@@ -18,11 +59,11 @@ pub struct Position {
     pub hand: [[u8; 5]; 2],
     /// The number of plies.
     pub ply: u16,
+    /// The move count of the position before the history, which is the last field of sfen.
+    pub start_move_number: u16,
 
-    /// Move history.
-    pub kif: [Move; MAX_PLY + 1],
-    /// The hash value of positions (including history positions).
-    pub hash: [(u64, u64); MAX_PLY + 1],
+    /// The history of the game, which is shared by copies of the position until one of them moves.
+    pub history: Arc<History>,
 
     /// The bitwise pawn flags that is used not to generate double-pawn (2fu) moves.
     pub pawn_flags: [u8; 2],
@@ -30,12 +71,12 @@ pub struct Position {
     pub piece_bb: [Bitboard; Piece::B_PAWN_X.as_usize() + 1],
     /// The bitboard for each player.
     pub player_bb: [Bitboard; 2],
-    /// The bitboard of pieces which are checking by neighbors.
-    pub adjacent_check_bb: [Bitboard; MAX_PLY + 1],
-    /// The bitboard of pieces which are checking not by neighbors.
-    pub long_check_bb: [Bitboard; MAX_PLY + 1],
-    /// The number of sequential check (including history positions).
-    pub sequent_check_count: [[u8; 2]; MAX_PLY + 1],
+
+    /// Whether a king reaching the opponent's back rank wins (try rule).
+    pub try_rule: bool,
+
+    /// The active HalfKP features, which are updated incrementally by `do_move` and `undo_move`.
+    pub accumulator: ::nnue::Accumulator,
 }
 
 #[pymethods]
@@ -49,21 +90,24 @@ impl Position {
         Ok(self.sfen(true))
     }
 
+    #[pyo3(text_signature = "($self, sfen)")]
     fn __setstate__(&mut self, sfen: &str) -> PyResult<()> {
-        self.set_sfen(&sfen);
-        Ok(())
+        Ok(self.set_sfen(sfen)?)
     }
 
     /// Copy the `position`
     ///
     /// Arguments:
     /// * `entire`: If true, historical positions are also copied.
+    #[pyo3(text_signature = "($self, entire)")]
     pub fn copy(&self, entire: bool) -> Position {
         if entire {
-            return *self;
+            return self.clone();
         }
 
         let mut position = Position::empty_board();
+        position.try_rule = self.try_rule;
+        position.start_move_number = self.get_move_number();
         position.side_to_move = self.side_to_move;
         for i in 0..SQUARE_NB {
             position.board[i] = self.board[i]
@@ -79,11 +123,13 @@ impl Position {
         for piece in &PIECE_ALL {
             position.piece_bb[piece.as_usize()] = self.piece_bb[piece.as_usize()];
         }
+        position.accumulator = self.accumulator;
 
-        position.hash[0] = self.hash[self.ply as usize];
-        position.adjacent_check_bb[0] = self.adjacent_check_bb[self.ply as usize];
-        position.long_check_bb[0] = self.long_check_bb[self.ply as usize];
-        position.sequent_check_count[0] = self.sequent_check_count[self.ply as usize];
+        let history = Arc::make_mut(&mut position.history);
+        history.hash[0] = self.history.hash[self.ply as usize];
+        history.adjacent_check_bb[0] = self.history.adjacent_check_bb[self.ply as usize];
+        history.long_check_bb[0] = self.history.long_check_bb[self.ply as usize];
+        history.sequent_check_count[0] = self.history.sequent_check_count[self.ply as usize];
 
         return position;
     }
@@ -124,9 +170,14 @@ impl Position {
     }
 
     /// Return the sfen representation of the position.
+    ///
+    /// Arguments:
+    /// * `history`: If true, the position before the history followed by `moves` is returned.
+    ///              Otherwise, the current position with its move count is returned.
+    #[pyo3(text_signature = "($self, history)")]
     pub fn sfen(&self, history: bool) -> String {
         if history {
-            let mut position = *self;
+            let mut position = self.clone();
 
             for _ in 0..self.ply {
                 position.undo_move();
@@ -139,7 +190,7 @@ impl Position {
             }
 
             for i in 0..self.ply {
-                sfen_position.push_str(&format!(" {}", self.kif[i as usize].sfen()));
+                sfen_position.push_str(&format!(" {}", self.history.kif[i as usize].sfen()));
             }
 
             return sfen_position;
@@ -148,172 +199,177 @@ impl Position {
         }
     }
 
+    /// Return the arguments of the USI `position` command, e.g. `startpos moves 5e4d`.
+    ///
+    /// The form `sfen <sfen> moves ...` is used if the game did not begin from the initial position.
+    pub fn usi_position(&self) -> String {
+        let sfen = self.sfen(true);
+        let (initial, moves) = match sfen.find(" moves") {
+            Some(index) => sfen.split_at(index),
+            None => (&sfen[..], ""),
+        };
+
+        if initial == START_POSITION_SFEN {
+            format!("startpos{}", moves)
+        } else {
+            format!("sfen {}{}", initial, moves)
+        }
+    }
+
     pub fn get_kif(&self) -> std::vec::Vec<String> {
-        self.kif[0..self.ply as usize].to_vec().into_iter().map(|x| x.sfen()).collect()
+        self.history.kif[0..self.ply as usize].to_vec().into_iter().map(|x| x.sfen()).collect()
     }
 
     pub fn get_csa_kif(&self) -> std::vec::Vec<String> {
-        self.kif[0..self.ply as usize].to_vec().into_iter().map(|x| x.csa()).collect()
+        self.history.kif[0..self.ply as usize].to_vec().into_iter().map(|x| x.csa()).collect()
     }
 
-    /// Set the position by sfen string.
+    /// Return the CSA representation of the position.
     ///
     /// Arguments:
-    /// * `sfen`: The sfen representation of a position.
-    /// * `incremental_update`: If false, historical variables (check bitboards, etc...) are not set.
-    pub fn _set_sfen_with_option(&mut self, sfen: &str, incremental_update: bool) {
-        // 初期化
-        for i in 0..SQUARE_NB {
-            self.board[i] = Piece::NO_PIECE;
-        }
-        for i in 0..2 {
-            for j in 0..5 {
-                self.hand[i][j] = 0;
-            }
-
-            self.pawn_flags[i] = 0;
+    /// * `history`: If true, the position before the history followed by the moves (e.g. `+5554OU`) is returned.
+    ///              Otherwise, only the current position is returned.
+    #[pyo3(text_signature = "($self, history)")]
+    pub fn csa(&self, history: bool) -> String {
+        if !history {
+            return self.get_csa_position();
         }
 
-        let mut square: usize = 0;
-        let mut promote: bool = false;
-
-        let mut sfen_split = sfen.split_whitespace();
-
-        // sfenから盤面を設定
-        for c in sfen_split.next().unwrap().chars() {
-            if c == '+' {
-                promote = true;
-                continue;
-            }
-
-            if c == '/' {
-                continue;
-            }
-
-            if c.is_ascii_digit() {
-                square += ((c as u8) - ('0' as u8)) as usize;
-                continue;
-            }
-
-            let mut piece = char_to_piece(c);
-
-            if promote {
-                piece = piece.get_promoted();
-            }
+        let mut position = self.clone();
 
-            self.board[square] = piece;
+        for _ in 0..self.ply {
+            position.undo_move();
+        }
 
-            if piece == Piece::W_PAWN {
-                self.pawn_flags[Color::WHITE.as_usize()] |= 1 << (square % 5);
-            } else if piece == Piece::B_PAWN {
-                self.pawn_flags[Color::BLACK.as_usize()] |= 1 << (square % 5);
-            }
+        let mut csa = position.get_csa_position();
 
-            promote = false;
-            square += 1;
+        for m in &self.history.kif {
+            csa.push_str(&format!("{}{}\n", csa_sign(m.get_piece().get_color()), m.csa()));
         }
 
-        // 手番を設定
-        if sfen_split.next() == Some("b") {
-            self.side_to_move = Color::WHITE;
-        } else {
-            self.side_to_move = Color::BLACK;
-        }
+        csa
+    }
 
-        // 持ち駒を設定
-        let mut count: u8 = 1;
-        for c in sfen_split.next().unwrap().chars() {
-            if c == '-' {
-                continue;
-            }
+    /// Set the position by the CSA representation, i.e. the board (`P1` to `P5`), hand pieces (`P+` and `P-`),
+    /// the side to move (`+` or `-`) and moves such as `+5554OU`.
+    ///
+    /// Comments and other information (lines beginning with `'`, `V`, `N`, `$`, `T` and `%`) are ignored.
+    ///
+    /// Raises `ValueError` if the CSA is malformed or contains an impossible move,
+    /// in which case the position is left unchanged.
+    #[pyo3(text_signature = "($self, csa)")]
+    pub fn set_csa(&mut self, csa: &str) -> Result<(), PositionError> {
+        let mut position = Position::empty_board();
+        position.try_rule = self.try_rule;
 
-            if c.is_ascii_digit() {
-                count = (c as u8) - ('0' as u8);
-                continue;
+        match position.parse_csa(csa) {
+            Ok(()) => {
+                *self = position;
+                Ok(())
             }
-
-            let piece = char_to_piece(c);
-            let color = piece.get_color();
-            let piece_type = piece.get_piece_type();
-            let hand_index = (piece_type.as_usize()) - 2;
-
-            self.hand[color.as_usize()][hand_index] = count;
-
-            count = 1;
+            Err(reason) => Err(PositionError(format!("invalid csa: {}", reason))),
         }
+    }
 
-        self.set_bitboard();
-        self.set_check_bb();
-        self.hash[0] = self.calculate_hash();
-
-        self.ply = 0;
-
-        sfen_split.next(); // sfenプロトコルで常に1が格納されているはずなので、読み飛ばす
-
-        if sfen_split.next() == Some("moves") {
-            loop {
-                let sfen_move = sfen_split.next();
+    /// Convert a CSA represented move such as `+5554OU` or `0033FU` to a `Move` struct instance.
+    ///
+    /// Raises `ValueError` if the move is malformed or impossible in the position.
+    #[pyo3(text_signature = "($self, csa)")]
+    pub fn csa_to_move(&self, csa: &str) -> Result<Move, PositionError> {
+        self.parse_csa_move(csa)
+            .map_err(|reason| PositionError(format!("invalid move `{}`: {}", csa, reason)))
+    }
 
-                if !sfen_move.is_some() {
-                    break;
-                }
+    /// Set the position by sfen string.
+    ///
+    /// Arguments:
+    /// * `sfen`: The sfen representation of a position.
+    /// * `incremental_update`: If false, historical variables (check bitboards, etc...) are not set.
+    ///
+    /// Raises `ValueError` if the sfen is malformed or contains an impossible move,
+    /// in which case the position is left unchanged.
+    #[pyo3(text_signature = "($self, sfen, incremental_update)")]
+    pub fn _set_sfen_with_option(
+        &mut self,
+        sfen: &str,
+        incremental_update: bool,
+    ) -> Result<(), PositionError> {
+        let mut position = Position::empty_board();
+        position.try_rule = self.try_rule;
 
-                let m = self.sfen_to_move(sfen_move.unwrap());
-                self._do_move_with_option(&m, incremental_update);
+        match position.parse_sfen(sfen, incremental_update) {
+            Ok(()) => {
+                *self = position;
+                Ok(())
             }
+            Err(reason) => Err(PositionError(format!("invalid sfen `{}`: {}", sfen, reason))),
         }
     }
 
     /// Set a position by the sfen.
-    pub fn set_sfen(&mut self, sfen: &str) {
-        self._set_sfen_with_option(sfen, true);
+    ///
+    /// The arguments of the USI `position` command such as `startpos moves 5e4d` are also accepted.
+    #[pyo3(text_signature = "($self, sfen)")]
+    pub fn set_sfen(&mut self, sfen: &str) -> Result<(), PositionError> {
+        self._set_sfen_with_option(sfen, true)
     }
 
     /// Set a position by the sfen, ignoring historical positions.
-    pub fn set_sfen_simple(&mut self, sfen: &str) {
-        self._set_sfen_with_option(sfen, false);
+    #[pyo3(text_signature = "($self, sfen)")]
+    pub fn set_sfen_simple(&mut self, sfen: &str) -> Result<(), PositionError> {
+        self._set_sfen_with_option(sfen, false)?;
         self.set_flags();
+
+        Ok(())
     }
 
     /// Set the position to the initial position.
     pub fn set_start_position(&mut self) {
-        self.set_sfen_without_startpos("");
+        self.set_sfen_without_startpos("").expect("the start position must be valid");
     }
 
     /// Set the position by sfen consisted only by moves.
-    pub fn set_sfen_without_startpos(&mut self, sfen: &str) {
-        static START_POSITION_SFEN: &str = "rbsgk/4p/5/P4/KGSBR b - 1";
+    #[pyo3(text_signature = "($self, sfen)")]
+    pub fn set_sfen_without_startpos(&mut self, sfen: &str) -> Result<(), PositionError> {
         let sfen_kif = format!("{} moves {}", START_POSITION_SFEN, sfen);
 
-        self.set_sfen(&sfen_kif);
+        self.set_sfen(&sfen_kif)
     }
 
     /// Set the position by sfen consisted only by moves, ignoring historical positions.
-    pub fn set_sfen_without_startpos_simple(&mut self, sfen: &str) {
-        static START_POSITION_SFEN: &str = "rbsgk/4p/5/P4/KGSBR b - 1";
+    #[pyo3(text_signature = "($self, sfen)")]
+    pub fn set_sfen_without_startpos_simple(&mut self, sfen: &str) -> Result<(), PositionError> {
         let sfen_kif = format!("{} moves {}", START_POSITION_SFEN, sfen);
 
-        self.set_sfen_simple(&sfen_kif);
+        self.set_sfen_simple(&sfen_kif)
     }
 
     /// Convert a sfen represented move to a `Move` struct instance.
-    pub fn sfen_to_move(&self, sfen: &str) -> Move {
-        if sfen.as_bytes()[1] as char == '*' {
-            let piece = char_to_piece(sfen.as_bytes()[0] as char)
-                .get_piece_type()
-                .get_piece(self.side_to_move);
-            let to = sfen_to_square(&sfen[2..4]);
+    ///
+    /// Raises `ValueError` if the move is malformed or impossible in the position.
+    #[pyo3(text_signature = "($self, sfen)")]
+    pub fn sfen_to_move(&self, sfen: &str) -> Result<Move, PositionError> {
+        self.parse_move(sfen)
+            .map_err(|reason| PositionError(format!("invalid move `{}`: {}", sfen, reason)))
+    }
 
-            Move::hand_move(piece, to)
-        } else {
-            let from = sfen_to_square(&sfen[0..2]);
-            let to = sfen_to_square(&sfen[2..4]);
-            let promotion = sfen.len() == 5;
-            let piece = self.board[from];
-            let capture_piece = self.board[to];
+    /// Check whether the sfen represented moves can be played in order from the position.
+    ///
+    /// The position itself is left unchanged.
+    ///
+    /// Returns:
+    /// * `None` if all moves are legal, otherwise `(index, reason)` of the first illegal move.
+    #[pyo3(text_signature = "($self, moves)")]
+    pub fn validate_moves(&self, moves: std::vec::Vec<String>) -> Option<(usize, String)> {
+        let mut position = self.clone();
 
-            Move::board_move(piece, from, to, promotion, capture_piece)
+        for (i, sfen) in moves.iter().enumerate() {
+            if let Err(reason) = position.validate_move(sfen) {
+                return Some((i, format!("`{}`: {}", sfen, reason)));
+            }
         }
+
+        None
     }
 
     pub fn get_side_to_move(&self) -> u8 {
@@ -324,11 +380,83 @@ impl Position {
         return self.ply;
     }
 
+    /// Return the move count of the current position, i.e. the number of the next move.
+    pub fn get_move_number(&self) -> u16 {
+        self.start_move_number + self.ply
+    }
+
+    /// Return the sorted indices of active HalfKP features, which are kept up to date by `do_move` and `undo_move`.
+    ///
+    /// Arguments:
+    /// * `perspective`: The color (0: WHITE, 1: BLACK) whose king square is used.
+    #[pyo3(text_signature = "($self, perspective)")]
+    pub fn get_halfkp_features(&self, perspective: u8) -> Result<std::vec::Vec<usize>, PositionError> {
+        Ok(self.accumulator.features(self, self.check_perspective(perspective)?))
+    }
+
+    /// Return `(removed, added)` HalfKP features by doing the move `m`,
+    /// or `None` if the king of `perspective` moves and all features have to be refreshed.
+    #[pyo3(text_signature = "($self, m, perspective)")]
+    pub fn get_halfkp_diff(
+        &self,
+        m: &Move,
+        perspective: u8,
+    ) -> Result<Option<::nnue::FeatureDiff>, PositionError> {
+        Ok(::nnue::halfkp_feature_diff(self, m, self.check_perspective(perspective)?))
+    }
+
     /// Generate legal moves.
     ///
     /// Note: A move that cause immediate checkmate by a pawn (Utifu-dume) is included.
-    pub fn generate_moves(&self) -> std::vec::Vec<Move> {
-        return self.generate_moves_with_option(true, true, false, false);
+    #[pyo3(name = "generate_moves")]
+    fn py_generate_moves(&self, py: Python) -> Result<std::vec::Vec<Move>, PositionError> {
+        self.check_position_is_set()?;
+
+        Ok(py.allow_threads(|| self.generate_moves()))
+    }
+
+    /// Enable or disable the try rule, where a king reaching the opponent's back rank wins.
+    ///
+    /// The rule is disabled by default, and is kept when a new sfen is set.
+    #[pyo3(text_signature = "($self, enabled)")]
+    pub fn set_try_rule(&mut self, enabled: bool) {
+        self.try_rule = enabled;
+    }
+
+    /// Whether the side which has just moved has won by the try rule,
+    /// i.e. its king is on the back rank of the side to move.
+    ///
+    /// Always false if the try rule is disabled.
+    pub fn is_try(&self) -> bool {
+        if !self.try_rule || self.side_to_move == Color::NO_COLOR {
+            return false;
+        }
+
+        let color = self.side_to_move.get_op_color();
+        let back_rank_bb: Bitboard = if color == Color::WHITE { 0b11111 } else { 0b11111 << 20 };
+
+        self.piece_bb[PieceType::KING.get_piece(color).as_usize()] & back_rank_bb != 0
+    }
+
+    /// Return the number of pieces of `color` (0: WHITE, 1: BLACK) attacking each square.
+    ///
+    /// Squares occupied by own pieces are counted as well, i.e. the counts include defenders.
+    #[pyo3(text_signature = "($self, color)")]
+    pub fn get_attack_counts(&self, color: u8) -> Result<std::vec::Vec<u8>, PositionError> {
+        Ok(self.attack_counts(check_color(color)?).to_vec())
+    }
+
+    /// Return the squares of pieces of `color` (0: WHITE, 1: BLACK) pinned to their own king.
+    #[pyo3(name = "pinned_pieces", text_signature = "($self, color)")]
+    fn py_pinned_pieces(&self, color: u8) -> Result<std::vec::Vec<usize>, PositionError> {
+        Ok(get_squares(self.pinned_pieces(check_color(color)?)))
+    }
+
+    /// Return the squares of pieces of `color` (0: WHITE, 1: BLACK)
+    /// whose move may discover a check to the opponent king.
+    #[pyo3(name = "discovered_check_candidates", text_signature = "($self, color)")]
+    fn py_discovered_check_candidates(&self, color: u8) -> Result<std::vec::Vec<usize>, PositionError> {
+        Ok(get_squares(self.discovered_check_candidates(check_color(color)?)))
     }
 
     /// Whether the king is in check.
@@ -341,9 +469,10 @@ impl Position {
         self.pawn_flags = [0; 2];
         self.piece_bb = [0; Piece::B_PAWN_X.as_usize() + 1];
         self.player_bb = [0; 2];
-        self.adjacent_check_bb = [0; MAX_PLY + 1];
-        self.long_check_bb = [0; MAX_PLY + 1];
-        self.sequent_check_count = [[0; 2]; MAX_PLY + 1];
+        let history = Arc::make_mut(&mut self.history);
+        history.adjacent_check_bb = vec![0; self.ply as usize + 1];
+        history.long_check_bb = vec![0; self.ply as usize + 1];
+        history.sequent_check_count = vec![[0; 2]; self.ply as usize + 1];
 
         for i in 0..SQUARE_NB {
             if self.board[i] == Piece::W_PAWN {
@@ -359,252 +488,114 @@ impl Position {
         }
 
         self.set_check_bb();
+        self.accumulator = ::nnue::Accumulator::new(self);
+    }
+
+    /// Do a move.
+    ///
+    /// Raises `ValueError` if the move cannot be done in the position.
+    #[pyo3(name = "do_move", text_signature = "($self, m)")]
+    fn py_do_move(&mut self, m: &Move) -> Result<(), PositionError> {
+        self.check_position_is_set()?;
+        self.check_move(m)
+            .map_err(|reason| PositionError(format!("cannot do `{}`: {}", m.sfen(), reason)))?;
+
+        self.do_move(m);
+
+        Ok(())
     }
 
     /// Do a move.
     ///
     /// Arguments:
-    /// * `move`: The move to do.
+    /// * `m`: The move to do.
     /// * `incremental_update`: If false, historical variables (check bitboards, etc...) are not set.
-    pub fn _do_move_with_option(&mut self, m: &Move, incremental_update: bool) {
-        assert!(m.get_capture_piece().get_piece_type() != PieceType::KING);
-
-        self.hash[self.ply as usize + 1] = self.hash[self.ply as usize];
+    ///
+    /// Raises `ValueError` if the move cannot be done in the position.
+    #[pyo3(name = "_do_move_with_option", text_signature = "($self, m, incremental_update)")]
+    fn py_do_move_with_option(&mut self, m: &Move, incremental_update: bool) -> Result<(), PositionError> {
+        self.check_position_is_set()?;
+        self.check_move(m)
+            .map_err(|reason| PositionError(format!("cannot do `{}`: {}", m.sfen(), reason)))?;
 
-        if m.is_hand() {
-            // 持ち駒を打つ場合
+        self._do_move_with_option(m, incremental_update);
 
-            self.board[m.get_to() as usize] = m.get_piece();
-            self.hand[self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2] -= 1;
+        Ok(())
+    }
 
-            // Bitboardの更新
-            self.piece_bb[m.get_piece().as_usize()] |= 1 << m.get_to();
-            self.player_bb[self.side_to_move.as_usize()] |= 1 << m.get_to();
+    /// Undo the move.
+    ///
+    /// Raises `ValueError` if there is no move to undo.
+    #[pyo3(name = "undo_move")]
+    fn py_undo_move(&mut self) -> Result<(), PositionError> {
+        if self.ply == 0 {
+            return Err(PositionError("there is no move to undo".to_string()));
+        }
 
-            // 二歩フラグの更新
-            if m.get_piece().get_piece_type() == PieceType::PAWN {
-                self.pawn_flags[self.side_to_move.as_usize()] |= 1 << (m.get_to() % 5);
-            }
+        self.undo_move();
 
-            // hash値の更新
-            self.hash[self.ply as usize + 1].0 ^= ::zobrist::BOARD_TABLE[m.get_to()][m.get_piece().as_usize()];
-            self.hash[self.ply as usize + 1].1 ^= ::zobrist::HAND_TABLE
-                [self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2]
-                [self.hand[self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2]
-                    as usize
-                    + 1];
-            self.hash[self.ply as usize + 1].1 ^= ::zobrist::HAND_TABLE
-                [self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2]
-                [self.hand[self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2]
-                    as usize];
-        } else {
-            // 盤上の駒を動かす場合
+        Ok(())
+    }
 
-            if m.get_capture_piece() != Piece::NO_PIECE {
-                self.hand[self.side_to_move.as_usize()]
-                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2] += 1;
+    /// Whether the position is now under the repetition (sennitite).
+    ///
+    /// Returns:
+    /// * `(repetition, my_check_repetition, op_check_repetition)`:
+    ///                                     If `check_repetition` is true,
+    ///                                     the one side has continued check moves which means
+    ///                                     immediate the other side's win.
+    pub fn is_repetition(&self) -> (bool, bool, bool) {
+        if self.ply == 0 {
+            return (false, false, false);
+        }
 
-                // Bitboardの更新
-                self.piece_bb[m.get_capture_piece().as_usize()] ^= 1 << m.get_to();
-                self.player_bb[self.side_to_move.get_op_color().as_usize()] ^= 1 << m.get_to();
+        let mut count = 0;
 
-                // 二歩フラグの更新
-                if m.get_capture_piece().get_piece_type() == PieceType::PAWN {
-                    self.pawn_flags[self.side_to_move.get_op_color().as_usize()] ^= 1 << (m.get_to() % 5);
-                }
+        let mut ply = self.ply as i32 - 4;
+        let mut my_check_repetition = false;
+        let mut op_check_repetition = false;
 
-                // hashの更新
-                self.hash[self.ply as usize + 1].0 ^=
-                    ::zobrist::BOARD_TABLE[m.get_to()][m.get_capture_piece().as_usize()];
-                self.hash[self.ply as usize + 1].1 ^= ::zobrist::HAND_TABLE
-                    [self.side_to_move.as_usize()]
-                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2][self
-                    .hand[self.side_to_move.as_usize()]
-                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2]
-                    as usize
-                    - 1];
-                self.hash[self.ply as usize + 1].1 ^= ::zobrist::HAND_TABLE
-                    [self.side_to_move.as_usize()]
-                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2][self
-                    .hand[self.side_to_move.as_usize()]
-                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2]
-                    as usize];
-            }
+        while ply >= 0 {
+            if self.history.hash[ply as usize] == self.history.hash[self.ply as usize] {
+                count += 1;
 
-            if m.is_promotion() {
-                self.board[m.get_to() as usize] = m.get_piece().get_promoted();
+                if count == 1 {
+                    if self.history.sequent_check_count[self.ply as usize][self.side_to_move.as_usize()]
+                        >= (self.ply + 1 - ply as u16) as u8 / 2
+                    {
+                        my_check_repetition = true;
+                    }
 
-                // 二歩フラグの更新
-                if m.get_piece().get_piece_type() == PieceType::PAWN {
-                    self.pawn_flags[self.side_to_move.as_usize()] ^= 1 << (m.get_to() % 5);
+                    if self.history.sequent_check_count[self.ply as usize]
+                        [self.side_to_move.get_op_color().as_usize()]
+                        >= (self.ply + 1 - ply as u16) as u8 / 2
+                    {
+                        op_check_repetition = true;
+                    }
                 }
-            } else {
-                self.board[m.get_to() as usize] = m.get_piece();
             }
 
-            self.board[m.get_from() as usize] = Piece::NO_PIECE;
-
-            // Bitboardの更新
-            // 移動先
-            self.piece_bb[self.board[m.get_to() as usize].as_usize()] |= 1 << m.get_to();
-            self.player_bb[self.side_to_move.as_usize()] |= 1 << m.get_to();
-            // 移動元
-            self.piece_bb[m.get_piece().as_usize()] ^= 1 << m.get_from();
-            self.player_bb[self.side_to_move.as_usize()] ^= 1 << m.get_from();
+            // 現在の局面の1手前から数え始めているので、3回 (+ 現在の局面 1回) で千日手
+            if count == 3 {
+                return (true, my_check_repetition, op_check_repetition);
+            }
 
-            // hash値の更新
-            self.hash[self.ply as usize + 1].0 ^=
-                ::zobrist::BOARD_TABLE[m.get_from()][m.get_piece().as_usize()];
-            self.hash[self.ply as usize + 1].0 ^=
-                ::zobrist::BOARD_TABLE[m.get_to()][self.board[m.get_to()].as_usize()];
+            ply -= 2; // 繰り返し回数は、同じ手番の過去局面だけを見れば良い
         }
 
-        self.hash[self.ply as usize + 1].0 ^= 1; // 手番bitの反転
-
-        // 棋譜に登録
-        self.kif[self.ply as usize] = *m;
-
-        // 1手進める
-        self.ply += 1;
-
-        // 手番を変える
-        self.side_to_move = self.side_to_move.get_op_color();
-
-        if incremental_update {
-            // 王手している駒を記録
-            self.set_check_bb();
-
-            // 連続王手のカウント
-            if self.adjacent_check_bb[self.ply as usize] != 0
-                || self.long_check_bb[self.ply as usize] != 0
-            {
-                self.sequent_check_count[self.ply as usize]
-                    [self.side_to_move.get_op_color().as_usize()] = self.sequent_check_count
-                    [self.ply as usize - 1][self.side_to_move.get_op_color().as_usize()]
-                    + 1;
-            } else {
-                self.sequent_check_count[self.ply as usize]
-                    [self.side_to_move.get_op_color().as_usize()] = 0;
-            }
-            self.sequent_check_count[self.ply as usize][self.side_to_move.as_usize()] =
-                self.sequent_check_count[self.ply as usize - 1][self.side_to_move.as_usize()];
-        }
-    }
-
-    /// Do a move.
-    pub fn do_move(&mut self, m: &Move) {
-        self._do_move_with_option(m, true);
-    }
-
-    /// Undo the move.
-    pub fn undo_move(&mut self) {
-        assert!(self.ply > 0);
-
-        // 手数を戻す
-        let m = self.kif[(self.ply - 1) as usize];
-        self.ply -= 1;
-
-        // 手番を戻す
-        self.side_to_move = self.side_to_move.get_op_color();
-
-        if m.is_hand() {
-            // 持ち駒を打った場合
-
-            self.board[m.get_to() as usize] = Piece::NO_PIECE;
-            self.hand[self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2] += 1;
-
-            // Bitboardのundo
-            self.piece_bb[m.get_piece().as_usize()] ^= 1 << m.get_to();
-            self.player_bb[self.side_to_move.as_usize()] ^= 1 << m.get_to();
-
-            // 二歩フラグのundo
-            if m.get_piece().get_piece_type() == PieceType::PAWN {
-                self.pawn_flags[self.side_to_move.as_usize()] ^= 1 << (m.get_to() % 5);
-            }
-        } else {
-            // 盤上の駒を動かした場合
-            assert!(self.board[m.get_to() as usize] != Piece::NO_PIECE);
-
-            // Bitboardのundo
-            // 移動先
-            self.piece_bb[self.board[m.get_to() as usize].as_usize()] ^= 1 << m.get_to();
-            self.player_bb[self.side_to_move.as_usize()] ^= 1 << m.get_to();
-            // 移動元
-            self.piece_bb[m.get_piece().as_usize()] |= 1 << m.get_from();
-            self.player_bb[self.side_to_move.as_usize()] |= 1 << m.get_from();
-
-            // 二歩フラグのundo
-            if m.get_piece().get_piece_type() == PieceType::PAWN && m.is_promotion() {
-                self.pawn_flags[self.side_to_move.as_usize()] |= 1 << (m.get_to() % 5);
-            }
-
-            self.board[m.get_to() as usize] = m.get_capture_piece();
-            self.board[m.get_from() as usize] = m.get_piece();
-
-            // 相手の駒を取っていた場合には、持ち駒から減らす
-            if m.get_capture_piece() != Piece::NO_PIECE {
-                self.hand[self.side_to_move.as_usize()]
-                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2] -= 1;
-
-                // Bitboardのundo
-                self.piece_bb[m.get_capture_piece().as_usize()] |= 1 << m.get_to();
-                self.player_bb[self.side_to_move.get_op_color().as_usize()] |= 1 << m.get_to();
-
-                // 二歩フラグのundo
-                if m.get_capture_piece().get_piece_type() == PieceType::PAWN {
-                    self.pawn_flags[self.side_to_move.get_op_color().as_usize()] |= 1 << (m.get_to() % 5);
-                }
-            }
-        }
+        return (false, false, false);
     }
 
-    /// Whether the position is now under the repetition (sennitite).
+    /// Classify the repetition of the position.
     ///
     /// Returns:
-    /// * `(repetition, my_check_repetition, op_check_repetition)`:
-    ///                                     If `check_repetition` is true,
-    ///                                     the one side has continued check moves which means
-    ///                                     immediate the other side's win.
-    pub fn is_repetition(&self) -> (bool, bool, bool) {
-        if self.ply == 0 {
-            return (false, false, false);
-        }
-
-        let mut count = 0;
-
-        let mut ply = self.ply as i32 - 4;
-        let mut my_check_repetition = false;
-        let mut op_check_repetition = false;
-
-        while ply >= 0 {
-            if self.hash[ply as usize] == self.hash[self.ply as usize] {
-                count += 1;
-
-                if count == 1 {
-                    if self.sequent_check_count[self.ply as usize][self.side_to_move.as_usize()]
-                        >= (self.ply + 1 - ply as u16) as u8 / 2
-                    {
-                        my_check_repetition = true;
-                    }
-
-                    if self.sequent_check_count[self.ply as usize]
-                        [self.side_to_move.get_op_color().as_usize()]
-                        >= (self.ply + 1 - ply as u16) as u8 / 2
-                    {
-                        op_check_repetition = true;
-                    }
-                }
-            }
-
-            // 現在の局面の1手前から数え始めているので、3回 (+ 現在の局面 1回) で千日手
-            if count == 3 {
-                return (true, my_check_repetition, op_check_repetition);
-            }
-
-            ply -= 2; // 繰り返し回数は、同じ手番の過去局面だけを見れば良い
-        }
-
-        return (false, false, false);
+    /// * `0`: Not a repetition.
+    /// * `1`: An ordinary repetition (draw).
+    /// * `2`: The opponent has continued checks (win of the side to move).
+    /// * `3`: The side to move has continued checks (loss of the side to move).
+    #[pyo3(name = "get_repetition_state")]
+    fn py_get_repetition_state(&self) -> u8 {
+        self.get_repetition_state().0
     }
 
     /// Return the number of repetition.
@@ -613,7 +604,7 @@ impl Position {
 
         let mut ply = self.ply as i32 - 4;
         while ply >= 0 {
-            if self.hash[ply as usize] == self.hash[self.ply as usize] {
+            if self.history.hash[ply as usize] == self.history.hash[self.ply as usize] {
                 count += 1;
             }
 
@@ -623,6 +614,13 @@ impl Position {
         return count;
     }
 
+    /// Return how many times the current position has occurred in the game, including the current one.
+    ///
+    /// The repetition (sennitite) is established when it reaches 4.
+    pub fn occurrences(&self) -> usize {
+        self.get_repetition() + 1
+    }
+
     /// Output a SVG format image.
     pub fn to_svg(&self) -> String {
         // ToDo:
@@ -712,11 +710,588 @@ impl Position {
             piece_bb: [0; Piece::B_PAWN_X.as_usize() + 1],
             player_bb: [0; 2],
             ply: 0,
-            kif: [NULL_MOVE; MAX_PLY + 1],
-            hash: [(0, 0); MAX_PLY + 1],
-            adjacent_check_bb: [0; MAX_PLY + 1],
-            long_check_bb: [0; MAX_PLY + 1],
-            sequent_check_count: [[0; 2]; MAX_PLY + 1],
+            start_move_number: 1,
+            history: Arc::new(History {
+                kif: std::vec::Vec::new(),
+                hash: vec![(0, 0)],
+                adjacent_check_bb: vec![0],
+                long_check_bb: vec![0],
+                sequent_check_count: vec![[0; 2]],
+            }),
+            try_rule: false,
+            accumulator: ::nnue::Accumulator::default(),
+        }
+    }
+
+    /// Set the position by sfen string.
+    ///
+    /// The arguments of the USI `position` command, e.g. `startpos moves 5e4d` or `sfen <sfen> moves 5e4d`,
+    /// are also accepted. The move count and `moves` can be omitted.
+    ///
+    /// Returns the reason if the sfen is malformed. The position may be partially set in that case.
+    fn parse_sfen(&mut self, sfen: &str, incremental_update: bool) -> Result<(), String> {
+        // 初期化
+        for i in 0..SQUARE_NB {
+            self.board[i] = Piece::NO_PIECE;
+        }
+        for i in 0..2 {
+            for j in 0..5 {
+                self.hand[i][j] = 0;
+            }
+
+            self.pawn_flags[i] = 0;
+        }
+
+        let mut sfen_split = sfen.split_whitespace().peekable();
+
+        // USIの `position` コマンドの形式
+        if sfen_split.peek() == Some(&"sfen") {
+            sfen_split.next();
+        }
+
+        if sfen_split.peek() == Some(&"startpos") {
+            sfen_split.next();
+
+            let rest: std::vec::Vec<&str> = sfen_split.collect();
+            return self.parse_sfen(&format!("{} {}", START_POSITION_SFEN, rest.join(" ")), incremental_update);
+        }
+
+        // sfenから盤面を設定
+        let board = sfen_split.next().ok_or("the board is missing")?;
+        let ranks: std::vec::Vec<&str> = board.split('/').collect();
+
+        if ranks.len() != 5 {
+            return Err(format!("the board has {} ranks", ranks.len()));
+        }
+
+        for (y, rank) in ranks.iter().enumerate() {
+            let mut x: usize = 0;
+            let mut promote: bool = false;
+
+            for c in rank.chars() {
+                if c == '+' {
+                    if promote {
+                        return Err("`+` is repeated".to_string());
+                    }
+
+                    promote = true;
+                    continue;
+                }
+
+                if c.is_ascii_digit() && !promote {
+                    x += ((c as u8) - b'0') as usize;
+                    continue;
+                }
+
+                let mut piece = char_to_piece(c);
+
+                if piece == Piece::NO_PIECE {
+                    return Err(format!("unknown piece `{}`", c));
+                }
+
+                if promote {
+                    if !piece.is_promotable() {
+                        return Err(format!("`{}` cannot be promoted", c));
+                    }
+
+                    piece = piece.get_promoted();
+                }
+
+                if x >= 5 {
+                    return Err(format!("the rank {} has more than 5 squares", y + 1));
+                }
+
+                let square = y * 5 + x;
+
+                self.board[square] = piece;
+
+                if piece == Piece::W_PAWN {
+                    self.pawn_flags[Color::WHITE.as_usize()] |= 1 << (square % 5);
+                } else if piece == Piece::B_PAWN {
+                    self.pawn_flags[Color::BLACK.as_usize()] |= 1 << (square % 5);
+                }
+
+                promote = false;
+                x += 1;
+            }
+
+            if promote {
+                return Err("`+` is not followed by a piece".to_string());
+            }
+
+            if x != 5 {
+                return Err(format!("the rank {} has {} squares", y + 1, x));
+            }
+        }
+
+        // 詰将棋などでは玉がない局面もあるので、2枚以上の場合だけをエラーとする
+        for king in [Piece::W_KING, Piece::B_KING].iter() {
+            if self.board.iter().filter(|piece| *piece == king).count() > 1 {
+                return Err("a player has more than one king".to_string());
+            }
+        }
+
+        // 手番を設定
+        match sfen_split.next() {
+            Some("b") => self.side_to_move = Color::WHITE,
+            Some("w") => self.side_to_move = Color::BLACK,
+            _ => return Err("the side to move must be `b` or `w`".to_string()),
+        }
+
+        // 持ち駒を設定
+        let mut digits = String::new();
+        for c in sfen_split.next().ok_or("the hand is missing")?.chars() {
+            if c == '-' {
+                continue;
+            }
+
+            if c.is_ascii_digit() {
+                digits.push(c);
+                continue;
+            }
+
+            let piece = char_to_piece(c);
+
+            if piece == Piece::NO_PIECE || piece.get_piece_type() == PieceType::KING {
+                return Err(format!("`{}` cannot be a hand piece", c));
+            }
+
+            // 個数が省略された場合は1
+            let count = if digits.is_empty() { 1 } else { digits.parse::<u8>().unwrap_or(0) };
+
+            if count == 0 || count > 2 {
+                return Err(format!("invalid count `{}` of `{}` in the hand", digits, c));
+            }
+
+            let color = piece.get_color();
+            let piece_type = piece.get_piece_type();
+            let hand_index = (piece_type.as_usize()) - 2;
+
+            self.hand[color.as_usize()][hand_index] += count;
+
+            // 持ち駒は各駒2枚まで
+            if self.hand[color.as_usize()][hand_index] > 2 {
+                return Err(format!("there are more than 2 pieces of `{}` in the hand", c));
+            }
+
+            digits.clear();
+        }
+
+        if !digits.is_empty() {
+            return Err(format!("the count `{}` is not followed by a hand piece", digits));
+        }
+
+        self.set_bitboard();
+        self.set_check_bb();
+        Arc::make_mut(&mut self.history).hash[0] = self.calculate_hash();
+        self.accumulator = ::nnue::Accumulator::new(self);
+
+        self.ply = 0;
+
+        // 手数を設定 (省略された場合は1)
+        self.start_move_number = 1;
+
+        if let Some(count) = sfen_split.next_if(|token| *token != "moves") {
+            self.start_move_number = match count.parse::<u16>() {
+                // 最大手数まで進めても u16 に収まる範囲に限る
+                Ok(number) if number >= 1 && number as usize + MAX_PLY <= u16::MAX as usize => number,
+                _ => return Err(format!("invalid move count `{}`", count)),
+            };
+        }
+
+        match sfen_split.next() {
+            Some("moves") => {
+                for sfen_move in sfen_split {
+                    let m = self.parse_move(sfen_move)?;
+                    self.check_move(&m)
+                        .map_err(|reason| format!("move `{}`: {}", sfen_move, reason))?;
+
+                    self._do_move_with_option(&m, incremental_update);
+                }
+            }
+            Some(token) => return Err(format!("unexpected `{}` after the move count", token)),
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Set the position by the CSA representation.
+    ///
+    /// Returns the reason if the CSA is malformed. The position may be partially set in that case.
+    fn parse_csa(&mut self, csa: &str) -> Result<(), String> {
+        // 指し手以外を読み込んだ局面
+        let mut initial = Position::empty_board();
+        let mut rows = [false; 5];
+        let mut moves = std::vec::Vec::new();
+
+        for statement in csa.lines().flat_map(|line| line.split(',')) {
+            let statement = statement.trim_start().trim_end_matches('\r');
+
+            if statement.trim_end().is_empty() || statement.starts_with(|c| "'VN$T%".contains(c)) {
+                continue;
+            }
+
+            if !statement.is_ascii() {
+                return Err(format!("`{}` contains a non-ASCII character", statement));
+            }
+
+            if statement.starts_with("P+") || statement.starts_with("P-") {
+                // 持ち駒
+                let color = csa_color(&statement[1..2]);
+                let pieces = statement[2..].trim_end();
+
+                if pieces.len() % 4 != 0 {
+                    return Err(format!("hand pieces `{}` are malformed", statement));
+                }
+
+                for i in 0..pieces.len() / 4 {
+                    let piece = &pieces[4 * i..4 * i + 4];
+                    let piece_type = csa_to_piece_type(&piece[2..]);
+
+                    if &piece[..2] != "00" || !HAND_PIECE_TYPE_ALL.contains(&piece_type) {
+                        return Err(format!("`{}` cannot be a hand piece", piece));
+                    }
+
+                    let count = &mut initial.hand[color.as_usize()][piece_type.as_usize() - 2];
+
+                    if *count == 2 {
+                        return Err(format!("there are more than 2 pieces of `{}` in the hand", &piece[2..]));
+                    }
+
+                    *count += 1;
+                }
+            } else if statement.starts_with('P') {
+                // 盤面の各段 (末尾の空白は省略されていてもよい)
+                let y = match statement.as_bytes().get(1) {
+                    Some(c) if (b'1'..=b'5').contains(c) => (c - b'1') as usize,
+                    _ => return Err(format!("`{}` is not a rank of the board", statement)),
+                };
+                let cells = format!("{:<15}", &statement[2..]);
+
+                if cells.len() != 15 {
+                    return Err(format!("the rank {} has more than 5 squares", y + 1));
+                }
+
+                for x in 0..5 {
+                    let cell = &cells[3 * x..3 * x + 3];
+
+                    if cell == " * " {
+                        continue;
+                    }
+
+                    let piece_type = csa_to_piece_type(&cell[1..]);
+
+                    if !"+-".contains(&cell[..1]) || piece_type == PieceType::NO_PIECE_TYPE {
+                        return Err(format!("unknown piece `{}`", cell));
+                    }
+
+                    initial.board[y * 5 + x] = piece_type.get_piece(csa_color(&cell[..1]));
+                }
+
+                rows[y] = true;
+            } else if statement.trim_end() == "+" || statement.trim_end() == "-" {
+                initial.side_to_move = csa_color(statement.trim_end());
+            } else if statement.starts_with('+') || statement.starts_with('-') {
+                moves.push(statement.trim_end());
+            } else {
+                return Err(format!("unknown statement `{}`", statement));
+            }
+        }
+
+        if rows.contains(&false) {
+            return Err("the board must have 5 ranks".to_string());
+        }
+
+        if initial.side_to_move == Color::NO_COLOR {
+            return Err("the side to move is missing".to_string());
+        }
+
+        self.parse_sfen(&initial.get_sfen_position(), true)?;
+
+        for csa_move in moves {
+            let m = self.parse_csa_move(csa_move)?;
+            self.check_move(&m).map_err(|reason| format!("move `{}`: {}", csa_move, reason))?;
+
+            self.do_move(&m);
+        }
+
+        Ok(())
+    }
+
+    /// Convert a CSA represented move to a `Move` struct instance.
+    ///
+    /// The sign of the player can be omitted. Returns the reason if the move is malformed.
+    fn parse_csa_move(&self, csa: &str) -> Result<Move, String> {
+        let body = if csa.starts_with('+') || csa.starts_with('-') {
+            if csa_color(&csa[..1]) != self.side_to_move {
+                return Err("it is not the turn of the player".to_string());
+            }
+
+            &csa[1..]
+        } else {
+            csa
+        };
+
+        if body.len() != 6 || !body.is_ascii() {
+            return Err("the move must be like `+5554OU` or `0033FU`".to_string());
+        }
+
+        let piece_type = csa_to_piece_type(&body[4..]);
+
+        if piece_type == PieceType::NO_PIECE_TYPE {
+            return Err(format!("unknown piece `{}`", &body[4..]));
+        }
+
+        let to = csa_to_square(&body[2..4]).ok_or("the destination square is malformed")?;
+
+        if &body[..2] == "00" {
+            let sfen = format!("{}*{}", piece_to_string(piece_type.get_piece(Color::WHITE)), square_to_sfen(to));
+
+            return self.parse_move(&sfen);
+        }
+
+        let from = csa_to_square(&body[..2]).ok_or("the source square is malformed")?;
+        let piece = self.board[from];
+
+        // 成る手では、移動後の駒が書かれる
+        let promotion = piece.is_promotable() && piece.is_raw() && piece.get_piece_type().get_promoted() == piece_type;
+
+        if piece != Piece::NO_PIECE && !promotion && piece.get_piece_type() != piece_type {
+            return Err(format!("the piece on the source square is not `{}`", &body[4..]));
+        }
+
+        let sfen = format!(
+            "{}{}{}",
+            square_to_sfen(from),
+            square_to_sfen(to),
+            if promotion { "+" } else { "" }
+        );
+
+        self.parse_move(&sfen)
+    }
+
+    /// Get the CSA representation of the position.
+    pub fn get_csa_position(&self) -> String {
+        let mut csa = String::new();
+
+        for y in 0..5 {
+            csa.push_str(&format!("P{}", y + 1));
+
+            for x in 0..5 {
+                let piece = self.board[y * 5 + x];
+
+                if piece == Piece::NO_PIECE {
+                    csa.push_str(" * ");
+                } else {
+                    csa.push_str(csa_sign(piece.get_color()));
+                    csa.push_str(CSA_PIECE[piece.get_piece_type().as_usize()]);
+                }
+            }
+
+            csa.push('\n');
+        }
+
+        for color in [Color::WHITE, Color::BLACK].iter() {
+            let mut hand = String::new();
+
+            for piece_type in HAND_PIECE_TYPE_ALL.iter() {
+                for _ in 0..self.hand[color.as_usize()][piece_type.as_usize() - 2] {
+                    hand.push_str("00");
+                    hand.push_str(CSA_PIECE[piece_type.as_usize()]);
+                }
+            }
+
+            if !hand.is_empty() {
+                csa.push_str(&format!("P{}{}\n", csa_sign(*color), hand));
+            }
+        }
+
+        csa.push_str(csa_sign(self.side_to_move));
+        csa.push('\n');
+
+        csa
+    }
+
+    /// Convert a sfen represented move to a `Move` struct instance.
+    ///
+    /// Returns the reason if the move is malformed.
+    fn parse_move(&self, sfen: &str) -> Result<Move, String> {
+        let bytes = sfen.as_bytes();
+
+        let is_square = |s: &[u8]| (b'1'..=b'5').contains(&s[0]) && (b'a'..=b'e').contains(&s[1]);
+
+        if bytes.len() == 4 && bytes[1] == b'*' {
+            let piece_type = char_to_piece(bytes[0] as char).get_piece_type();
+
+            if !HAND_PIECE_TYPE_ALL.contains(&piece_type) {
+                return Err(format!("`{}` is not a hand piece", bytes[0] as char));
+            }
+            if !is_square(&bytes[2..4]) {
+                return Err(format!("`{}` is not a square", &sfen[2..4]));
+            }
+
+            let piece = piece_type.get_piece(self.side_to_move);
+            let to = sfen_to_square(&sfen[2..4]);
+
+            Ok(Move::hand_move(piece, to))
+        } else if (bytes.len() == 4 || (bytes.len() == 5 && bytes[4] == b'+'))
+            && is_square(&bytes[0..2])
+            && is_square(&bytes[2..4])
+        {
+            let from = sfen_to_square(&sfen[0..2]);
+            let to = sfen_to_square(&sfen[2..4]);
+            let promotion = sfen.len() == 5;
+            let piece = self.board[from];
+            let capture_piece = self.board[to];
+
+            if piece == Piece::NO_PIECE {
+                return Err("there is no piece on the source square".to_string());
+            }
+
+            Ok(Move::board_move(piece, from, to, promotion, capture_piece))
+        } else {
+            Err("the move is malformed".to_string())
+        }
+    }
+
+    /// Check whether the move can be done without breaking the position.
+    ///
+    /// This does not check the legality regarding checks.
+    /// Returns the reason if the move cannot be done.
+    pub fn check_move(&self, m: &Move) -> Result<(), String> {
+        if self.ply as usize >= MAX_PLY {
+            return Err(format!("the number of plies exceeds {}", MAX_PLY));
+        }
+
+        if m.is_null_move() {
+            return Err("the null move cannot be done".to_string());
+        }
+
+        if m.get_piece().get_color() != self.side_to_move {
+            return Err("the piece is not of the side to move".to_string());
+        }
+
+        if m.is_hand() {
+            if self.hand[self.side_to_move.as_usize()][m.get_hand_index()] == 0 {
+                return Err("the piece is not in hand".to_string());
+            }
+
+            if self.board[m.get_to()] != Piece::NO_PIECE {
+                return Err("the destination is not empty".to_string());
+            }
+
+            if m.get_piece().get_piece_type() == PieceType::PAWN
+                && self.pawn_flags[self.side_to_move.as_usize()] & (1 << (m.get_to() % 5)) != 0
+            {
+                return Err("two pawns on the same file (nifu)".to_string());
+            }
+        } else {
+            if self.board[m.get_from()] != m.get_piece() {
+                return Err("the piece is not on the source square".to_string());
+            }
+
+            if self.board[m.get_to()] != m.get_capture_piece() {
+                return Err("the captured piece is not on the destination".to_string());
+            }
+
+            if m.get_capture_piece().get_color() == self.side_to_move {
+                return Err("the move captures an own piece".to_string());
+            }
+
+            if m.get_capture_piece().get_piece_type() == PieceType::KING {
+                return Err("the move captures the king".to_string());
+            }
+
+            if self.is_hand_full(m) {
+                return Err("the hand already has 2 pieces of the captured piece".to_string());
+            }
+
+            if m.is_promotion() && !m.get_piece().is_promotable() {
+                return Err("the piece cannot be promoted".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the move captures a piece of which the side to move already has 2 in hand.
+    ///
+    /// This happens only in positions with more than 2 pieces of a type, which sfen does not forbid.
+    fn is_hand_full(&self, m: &Move) -> bool {
+        let capture_piece = m.get_capture_piece();
+
+        capture_piece != Piece::NO_PIECE
+            && self.hand[self.side_to_move.as_usize()][capture_piece.get_piece_type().get_raw().as_usize() - 2] >= 2
+    }
+
+    /// Do the sfen represented move if it is legal.
+    ///
+    /// Returns the reason if the move is illegal, in which case the position is left unchanged.
+    fn validate_move(&mut self, sfen: &str) -> Result<(), String> {
+        self.check_position_is_set().map_err(|error| error.0)?;
+
+        let m = self.parse_move(sfen)?;
+        self.check_move(&m)?;
+
+        if !self.generate_moves().contains(&m) {
+            return Err("the move is not legal in the position".to_string());
+        }
+
+        self.do_move(&m);
+
+        if self.is_pawn_drop_mate() {
+            self.undo_move();
+            return Err("checkmate by a pawn drop (uchifuzume)".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Convert the perspective of HalfKP features given from Python, whose king must be on the board.
+    fn check_perspective(&self, perspective: u8) -> Result<Color, PositionError> {
+        let color = check_color(perspective)?;
+
+        if self.piece_bb[PieceType::KING.get_piece(color).as_usize()] == 0 {
+            return Err(PositionError(format!("the player {} has no king", perspective)));
+        }
+
+        Ok(color)
+    }
+
+    /// Return an error if the position is not set yet (e.g. just after `Position()`).
+    pub fn check_position_is_set(&self) -> Result<(), PositionError> {
+        if self.side_to_move == Color::NO_COLOR {
+            return Err(PositionError("the position is not set".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Generate legal moves.
+    ///
+    /// Note: A move that cause immediate checkmate by a pawn (Utifu-dume) is included.
+    pub fn generate_moves(&self) -> std::vec::Vec<Move> {
+        return self.generate_moves_with_option(true, true, false, false);
+    }
+
+    /// Generate legal moves into `moves`, which is cleared first.
+    ///
+    /// Reusing the same vector avoids a heap allocation for every call.
+    pub fn generate_moves_into(&self, moves: &mut std::vec::Vec<Move>) {
+        self.generate_moves_with_option_into(true, true, false, false, moves);
+    }
+
+    /// Whether the last move is a checkmate by a pawn drop (Utifu-dume), which is illegal.
+    pub fn is_pawn_drop_mate(&self) -> bool {
+        match self.history.kif.last() {
+            Some(m) => {
+                m.is_hand()
+                    && m.get_piece().get_piece_type() == PieceType::PAWN
+                    && self.is_in_check()
+                    && self.generate_moves().is_empty()
+            }
+            None => false,
         }
     }
 
@@ -729,53 +1304,197 @@ impl Position {
         self.player_bb[Color::WHITE.as_usize()] = 0;
         self.player_bb[Color::BLACK.as_usize()] = 0;
 
-        // 盤上の駒に対応する場所のbitを立てる
-        for i in 0..SQUARE_NB {
-            if self.board[i] != Piece::NO_PIECE {
-                self.piece_bb[self.board[i].as_usize()] |= 1 << i;
-                self.player_bb[self.board[i].get_color().as_usize()] |= 1 << i;
+        // 盤上の駒に対応する場所のbitを立てる
+        for i in 0..SQUARE_NB {
+            if self.board[i] != Piece::NO_PIECE {
+                self.piece_bb[self.board[i].as_usize()] |= 1 << i;
+                self.player_bb[self.board[i].get_color().as_usize()] |= 1 << i;
+            }
+        }
+    }
+
+    /// Set check bitboards.
+    fn set_check_bb(&mut self) {
+        let (adjacent_check_bb, long_check_bb) = self.calculate_check_bb();
+
+        let history = Arc::make_mut(&mut self.history);
+        history.adjacent_check_bb[self.ply as usize] = adjacent_check_bb;
+        history.long_check_bb[self.ply as usize] = long_check_bb;
+    }
+
+    /// Return the bitboards of pieces which are checking by neighbors and not by neighbors.
+    ///
+    /// Both are empty if the side to move has no king.
+    fn calculate_check_bb(&self) -> (Bitboard, Bitboard) {
+        let mut adjacent_check_bb = 0;
+        let mut long_check_bb = 0;
+
+        let king_bb = self.piece_bb[PieceType::KING.get_piece(self.side_to_move).as_usize()];
+
+        if king_bb == 0 {
+            return (0, 0);
+        }
+
+        let king_square = get_square(king_bb);
+
+        for piece_type in PIECE_TYPE_ALL.iter() {
+            let check_bb = adjacent_attack(king_square, piece_type.get_piece(self.side_to_move))
+                & self.piece_bb[piece_type.get_piece(self.side_to_move.get_op_color()).as_usize()];
+
+            if check_bb != 0 {
+                adjacent_check_bb |= check_bb;
+            }
+        }
+
+        let player_bb =
+            self.player_bb[Color::WHITE.as_usize()] | self.player_bb[Color::BLACK.as_usize()];
+
+        // 角による王手
+        let bishop_check_bb = bishop_attack(king_square, player_bb);
+        long_check_bb |= bishop_check_bb
+            & self.piece_bb
+                [PieceType::BISHOP.get_piece(self.side_to_move.get_op_color()).as_usize()];
+        long_check_bb |= bishop_check_bb
+            & self.piece_bb
+                [PieceType::BISHOP_X.get_piece(self.side_to_move.get_op_color()).as_usize()];
+
+        // 飛車による王手
+        let rook_check_bb = rook_attack(king_square, player_bb);
+        long_check_bb |= rook_check_bb
+            & self.piece_bb[PieceType::ROOK.get_piece(self.side_to_move.get_op_color()).as_usize()];
+        long_check_bb |= rook_check_bb
+            & self.piece_bb
+                [PieceType::ROOK_X.get_piece(self.side_to_move.get_op_color()).as_usize()];
+
+        (adjacent_check_bb, long_check_bb)
+    }
+
+    /// Return the squares attacked by the piece on `square`.
+    pub fn get_attack_bb(&self, square: usize) -> Bitboard {
+        let piece = self.board[square];
+
+        if piece == Piece::NO_PIECE {
+            return 0;
+        }
+
+        let player_bb =
+            self.player_bb[Color::WHITE.as_usize()] | self.player_bb[Color::BLACK.as_usize()];
+
+        let mut attack_bb = adjacent_attack(square, piece);
+
+        match piece.get_piece_type() {
+            PieceType::BISHOP | PieceType::BISHOP_X => attack_bb |= bishop_attack(square, player_bb),
+            PieceType::ROOK | PieceType::ROOK_X => attack_bb |= rook_attack(square, player_bb),
+            _ => {}
+        }
+
+        attack_bb
+    }
+
+    /// Classify the repetition of the position.
+    ///
+    /// The repetition is established when the position has occurred four times, and
+    /// a side is regarded as the checking side only if all of its moves since the first occurrence were checks.
+    /// If both sides have continued checks, the side to move is regarded as the checking side.
+    pub fn get_repetition_state(&self) -> Repetition {
+        let current_hash = self.history.hash[self.ply as usize];
+
+        let mut count = 0;
+        let mut first_ply = self.ply as i32 - 4;
+
+        let mut ply = first_ply;
+        while ply >= 0 && count < 3 {
+            if self.history.hash[ply as usize] == current_hash {
+                count += 1;
+                first_ply = ply;
+            }
+
+            ply -= 2; // 繰り返し回数は、同じ手番の過去局面だけを見れば良い
+        }
+
+        if count < 3 {
+            return Repetition::NONE;
+        }
+
+        // 最初に現れた局面から現局面までに、各手番が指した手の数
+        let move_count = (self.ply as usize - first_ply as usize) / 2;
+        let sequent_check_count = self.history.sequent_check_count[self.ply as usize];
+
+        if sequent_check_count[self.side_to_move.as_usize()] as usize >= move_count {
+            Repetition::LOSE
+        } else if sequent_check_count[self.side_to_move.get_op_color().as_usize()] as usize >= move_count {
+            Repetition::WIN
+        } else {
+            Repetition::DRAW
+        }
+    }
+
+    /// Return the pieces which are the only piece between the king of `king_color`
+    /// and a sliding piece (bishop, rook and their promotions) of `slider_color`.
+    fn slider_blockers(&self, king_color: Color, slider_color: Color) -> Bitboard {
+        let king_bb = self.piece_bb[PieceType::KING.get_piece(king_color).as_usize()];
+
+        if king_bb == 0 {
+            return 0;
+        }
+
+        let king_square = get_square(king_bb);
+        let player_bb =
+            self.player_bb[Color::WHITE.as_usize()] | self.player_bb[Color::BLACK.as_usize()];
+
+        let bishops = self.piece_bb[PieceType::BISHOP.get_piece(slider_color).as_usize()]
+            | self.piece_bb[PieceType::BISHOP_X.get_piece(slider_color).as_usize()];
+        let rooks = self.piece_bb[PieceType::ROOK.get_piece(slider_color).as_usize()]
+            | self.piece_bb[PieceType::ROOK_X.get_piece(slider_color).as_usize()];
+
+        // 間に駒がないとしたときに玉に利いている飛び駒
+        let mut snipers = (bishop_attack(king_square, 0) & bishops) | (rook_attack(king_square, 0) & rooks);
+
+        let mut blockers: Bitboard = 0;
+
+        while snipers != 0 {
+            let sniper = get_square(snipers);
+            snipers &= snipers - 1;
+
+            let between = between_bb(king_square, sniper) & player_bb;
+
+            if get_counts(between) == 1 {
+                blockers |= between;
             }
         }
+
+        blockers
     }
 
-    /// Set check bitboards.
-    fn set_check_bb(&mut self) {
-        self.adjacent_check_bb[self.ply as usize] = 0;
-        self.long_check_bb[self.ply as usize] = 0;
+    /// Return the pieces of `color` pinned to their own king.
+    pub fn pinned_pieces(&self, color: Color) -> Bitboard {
+        self.slider_blockers(color, color.get_op_color()) & self.player_bb[color.as_usize()]
+    }
 
-        let king_square =
-            get_square(self.piece_bb[PieceType::KING.get_piece(self.side_to_move).as_usize()]);
+    /// Return the pieces of `color` whose move may discover a check to the opponent king.
+    pub fn discovered_check_candidates(&self, color: Color) -> Bitboard {
+        self.slider_blockers(color.get_op_color(), color) & self.player_bb[color.as_usize()]
+    }
 
-        assert!(king_square < SQUARE_NB);
+    /// Return the number of pieces of `color` attacking each square.
+    pub fn attack_counts(&self, color: Color) -> [u8; SQUARE_NB] {
+        let mut counts = [0; SQUARE_NB];
 
-        for piece_type in PIECE_TYPE_ALL.iter() {
-            let check_bb = adjacent_attack(king_square, piece_type.get_piece(self.side_to_move))
-                & self.piece_bb[piece_type.get_piece(self.side_to_move.get_op_color()).as_usize()];
+        let mut player_bb = self.player_bb[color.as_usize()];
 
-            if check_bb != 0 {
-                self.adjacent_check_bb[self.ply as usize] |= check_bb;
-            }
-        }
+        while player_bb != 0 {
+            let square = get_square(player_bb);
+            player_bb &= player_bb - 1;
 
-        let player_bb =
-            self.player_bb[Color::WHITE.as_usize()] | self.player_bb[Color::BLACK.as_usize()];
+            let mut attack_bb = self.get_attack_bb(square);
 
-        // 角による王手
-        let bishop_check_bb = bishop_attack(king_square, player_bb);
-        self.long_check_bb[self.ply as usize] |= bishop_check_bb
-            & self.piece_bb
-                [PieceType::BISHOP.get_piece(self.side_to_move.get_op_color()).as_usize()];
-        self.long_check_bb[self.ply as usize] |= bishop_check_bb
-            & self.piece_bb
-                [PieceType::BISHOP_X.get_piece(self.side_to_move.get_op_color()).as_usize()];
+            while attack_bb != 0 {
+                counts[get_square(attack_bb)] += 1;
+                attack_bb &= attack_bb - 1;
+            }
+        }
 
-        // 飛車による王手
-        let rook_check_bb = rook_attack(king_square, player_bb);
-        self.long_check_bb[self.ply as usize] |= rook_check_bb
-            & self.piece_bb[PieceType::ROOK.get_piece(self.side_to_move.get_op_color()).as_usize()];
-        self.long_check_bb[self.ply as usize] |= rook_check_bb
-            & self.piece_bb
-                [PieceType::ROOK_X.get_piece(self.side_to_move.get_op_color()).as_usize()];
+        counts
     }
 
     /// Calculate the hash from scratch.
@@ -804,16 +1523,16 @@ impl Position {
     }
 
     /// Get the hash.
-    fn get_hash(&self) -> (u64, u64) {
-        return self.hash[self.ply as usize];
+    pub fn get_hash(&self) -> (u64, u64) {
+        return self.history.hash[self.ply as usize];
     }
 
     pub fn get_adjacent_check_bb(&self) -> Bitboard {
-        return self.adjacent_check_bb[self.ply as usize];
+        return self.history.adjacent_check_bb[self.ply as usize];
     }
 
     pub fn get_long_check_bb(&self) -> Bitboard {
-        return self.long_check_bb[self.ply as usize];
+        return self.history.long_check_bb[self.ply as usize];
     }
 
     pub fn get_check_bb(&self) -> Bitboard {
@@ -862,20 +1581,21 @@ impl Position {
 
         let mut capture_flag = false;
 
-        for piece_type in &HAND_PIECE_TYPE_ALL {
-            if self.hand[Color::WHITE.as_usize()][piece_type.as_usize() - 2] > 0 {
-                sfen_position.push_str(
-                    &self.hand[Color::WHITE.as_usize()][piece_type.as_usize() - 2].to_string(),
-                );
-                sfen_position.push_str(&piece_to_string(piece_type.get_piece(Color::WHITE)));
-                capture_flag = true;
-            }
-            if self.hand[Color::BLACK.as_usize()][piece_type.as_usize() - 2] > 0 {
-                sfen_position.push_str(
-                    &self.hand[Color::BLACK.as_usize()][piece_type.as_usize() - 2].to_string(),
-                );
-                sfen_position.push_str(&piece_to_string(piece_type.get_piece(Color::BLACK)));
-                capture_flag = true;
+        // 持ち駒は先手、後手の順に、それぞれ飛、角、金、銀、歩の順で表す (1枚の場合は枚数を省略する)
+        static HAND_ORDER: [PieceType; 5] =
+            [PieceType::ROOK, PieceType::BISHOP, PieceType::GOLD, PieceType::SILVER, PieceType::PAWN];
+
+        for color in [Color::WHITE, Color::BLACK].iter() {
+            for piece_type in HAND_ORDER.iter() {
+                let count = self.hand[color.as_usize()][piece_type.as_usize() - 2];
+
+                if count > 1 {
+                    sfen_position.push_str(&count.to_string());
+                }
+                if count > 0 {
+                    sfen_position.push_str(&piece_to_string(piece_type.get_piece(*color)));
+                    capture_flag = true;
+                }
             }
         }
 
@@ -884,7 +1604,7 @@ impl Position {
         }
 
         sfen_position.push(' ');
-        sfen_position.push('1');
+        sfen_position.push_str(&self.get_move_number().to_string());
 
         return sfen_position;
     }
@@ -896,6 +1616,8 @@ impl Position {
     /// * `is_hand`: If true, moves using hand pieces (prisoners) are generated.
     /// * `allow_illegal`: If true, illegal moves (ignoring check) are generated.
     /// * `check_drop_only`: If true, only hand moves with check are generated.
+    ///
+    /// No move is generated if the game has been decided by the try rule.
     pub fn generate_moves_with_option(
         &self,
         is_board: bool,
@@ -905,6 +1627,32 @@ impl Position {
     ) -> std::vec::Vec<Move> {
         let mut moves: Vec<Move> = Vec::new();
 
+        self.generate_moves_with_option_into(
+            is_board,
+            is_hand,
+            allow_illegal,
+            check_drop_only,
+            &mut moves,
+        );
+
+        return moves;
+    }
+
+    /// Generate moves into `moves`, which is cleared first. See `generate_moves_with_option` for the arguments.
+    pub fn generate_moves_with_option_into(
+        &self,
+        is_board: bool,
+        is_hand: bool,
+        allow_illegal: bool,
+        check_drop_only: bool,
+        moves: &mut std::vec::Vec<Move>,
+    ) {
+        moves.clear();
+
+        if self.is_try() {
+            return;
+        }
+
         if is_board {
             let mut player_bb: Bitboard = self.player_bb[self.side_to_move.as_usize()];
 
@@ -915,8 +1663,8 @@ impl Position {
                 // 両王手がかかっているときは，玉を逃げる以外は非合法手
                 if !allow_illegal
                     && get_counts(
-                        self.adjacent_check_bb[self.ply as usize]
-                            | self.long_check_bb[self.ply as usize],
+                        self.history.adjacent_check_bb[self.ply as usize]
+                            | self.history.long_check_bb[self.ply as usize],
                     ) > 1
                 {
                     if self.board[i].get_piece_type() != PieceType::KING {
@@ -934,9 +1682,9 @@ impl Position {
 
                         // 近接王手がかかっていて，玉以外を動かす場合には，王手している駒を取るしかない
                         if !allow_illegal
-                            && self.adjacent_check_bb[self.ply as usize] != 0
+                            && self.history.adjacent_check_bb[self.ply as usize] != 0
                             && self.board[i].get_piece_type() != PieceType::KING
-                            && (self.adjacent_check_bb[self.ply as usize] & (1 << move_to)) == 0
+                            && (self.history.adjacent_check_bb[self.ply as usize] & (1 << move_to)) == 0
                         {
                             move_tos &= move_tos - 1;
                             continue;
@@ -993,9 +1741,9 @@ impl Position {
                         let move_to: usize = get_square(move_tos);
 
                         if !allow_illegal
-                            && self.adjacent_check_bb[self.ply as usize] != 0
+                            && self.history.adjacent_check_bb[self.ply as usize] != 0
                             && self.board[i].get_piece_type() != PieceType::KING
-                            && (self.adjacent_check_bb[self.ply as usize] & (1 << move_to)) == 0
+                            && (self.history.adjacent_check_bb[self.ply as usize] & (1 << move_to)) == 0
                         {
                             move_tos &= move_tos - 1;
                             continue;
@@ -1041,9 +1789,9 @@ impl Position {
                         let move_to: usize = get_square(move_tos);
 
                         if !allow_illegal
-                            && self.adjacent_check_bb[self.ply as usize] != 0
+                            && self.history.adjacent_check_bb[self.ply as usize] != 0
                             && self.board[i].get_piece_type() != PieceType::KING
-                            && (self.adjacent_check_bb[self.ply as usize] & (1 << move_to)) == 0
+                            && (self.history.adjacent_check_bb[self.ply as usize] & (1 << move_to)) == 0
                         {
                             move_tos &= move_tos - 1;
                             continue;
@@ -1082,7 +1830,7 @@ impl Position {
         }
 
         // 近接駒に王手されている場合、持ち駒を打つ手は全て非合法手
-        if is_hand && (allow_illegal || self.adjacent_check_bb[self.ply as usize] == 0) {
+        if is_hand && (allow_illegal || self.history.adjacent_check_bb[self.ply as usize] == 0) {
             // 駒のない升を列挙
             let empty_squares: Bitboard = ONE_BB
                 ^ (self.player_bb[Color::WHITE.as_usize()]
@@ -1094,11 +1842,15 @@ impl Position {
 
                     if check_drop_only {
                         // 王手となる手のみを生成
-                        let op_king_square = get_square(
-                            self.piece_bb[PieceType::KING
-                                .get_piece(self.side_to_move.get_op_color())
-                                .as_usize()],
-                        );
+                        let op_king_bb = self.piece_bb
+                            [PieceType::KING.get_piece(self.side_to_move.get_op_color()).as_usize()];
+
+                        // 相手の玉がなければ王手はない
+                        if op_king_bb == 0 {
+                            continue;
+                        }
+
+                        let op_king_square = get_square(op_king_bb);
 
                         let mut check_squares: Bitboard = adjacent_attack(
                             op_king_square,
@@ -1147,10 +1899,19 @@ impl Position {
             }
         }
 
+        let generated_num = moves.len();
+
+        ::movegen_stats::record(|stats| {
+            let drop_num = moves.iter().filter(|m| m.is_hand()).count() as u64;
+
+            stats.board_moves += generated_num as u64 - drop_num;
+            stats.drops += drop_num;
+        });
+
         // 非合法手を取り除く
         if !allow_illegal {
-            let king_square =
-                get_square(self.piece_bb[PieceType::KING.get_piece(self.side_to_move).as_usize()]);
+            let king_bb = self.piece_bb[PieceType::KING.get_piece(self.side_to_move).as_usize()];
+            let king_square = get_square(king_bb);
 
             let mut index: usize = 0;
 
@@ -1160,6 +1921,16 @@ impl Position {
                 }
 
                 let is_legal = |m: Move| -> bool {
+                    // 同じ駒が3枚以上ある局面では、持ち駒が3枚になる手は指せない
+                    if self.is_hand_full(&m) {
+                        return false;
+                    }
+
+                    // 玉がなければ王手を考える必要はない
+                    if king_bb == 0 {
+                        return true;
+                    }
+
                     if m.is_hand() {
                         // 持ち駒を打つ場合
                         let player_bb: Bitboard = self.player_bb[Color::WHITE.as_usize()]
@@ -1255,12 +2026,12 @@ impl Position {
                             }
                         } else {
                             // 王以外を動かす場合
-                            if get_counts(self.adjacent_check_bb[self.ply as usize]) > 1 {
+                            if get_counts(self.history.adjacent_check_bb[self.ply as usize]) > 1 {
                                 // 近接駒に両王手されている場合は玉を動かさないといけない
                                 return false;
-                            } else if get_counts(self.adjacent_check_bb[self.ply as usize]) == 1 {
+                            } else if get_counts(self.history.adjacent_check_bb[self.ply as usize]) == 1 {
                                 // 王手している近接駒を取る手でないといけない
-                                if self.adjacent_check_bb[self.ply as usize] & (1 << m.get_to()) == 0 {
+                                if self.history.adjacent_check_bb[self.ply as usize] & (1 << m.get_to()) == 0 {
                                     return false;
                                 }
                             }
@@ -1270,56 +2041,276 @@ impl Position {
                                 | (1 << m.get_to()))
                                 ^ (1 << m.get_from());
 
-                            // 角による王手
-                            let bishop_check_bb =
-                                bishop_attack(king_square, player_bb) & !(1 << m.get_to());
-                            if bishop_check_bb
-                                & self.piece_bb[PieceType::BISHOP
-                                    .get_piece(self.side_to_move.get_op_color())
-                                    .as_usize()]
-                                != 0
-                                || bishop_check_bb
-                                    & self.piece_bb[PieceType::BISHOP_X
-                                        .get_piece(self.side_to_move.get_op_color())
-                                        .as_usize()]
-                                    != 0
-                            {
-                                return false;
-                            }
+                            // 角による王手
+                            let bishop_check_bb =
+                                bishop_attack(king_square, player_bb) & !(1 << m.get_to());
+                            if bishop_check_bb
+                                & self.piece_bb[PieceType::BISHOP
+                                    .get_piece(self.side_to_move.get_op_color())
+                                    .as_usize()]
+                                != 0
+                                || bishop_check_bb
+                                    & self.piece_bb[PieceType::BISHOP_X
+                                        .get_piece(self.side_to_move.get_op_color())
+                                        .as_usize()]
+                                    != 0
+                            {
+                                return false;
+                            }
+
+                            // 飛車による王手
+                            let rook_check_bb = rook_attack(king_square, player_bb) & !(1 << m.get_to());
+
+                            if rook_check_bb
+                                & self.piece_bb[PieceType::ROOK
+                                    .get_piece(self.side_to_move.get_op_color())
+                                    .as_usize()]
+                                != 0
+                                || rook_check_bb
+                                    & self.piece_bb[PieceType::ROOK_X
+                                        .get_piece(self.side_to_move.get_op_color())
+                                        .as_usize()]
+                                    != 0
+                            {
+                                return false;
+                            }
+                        }
+                    }
+
+                    return true;
+                }(moves[index]);
+
+                if !is_legal {
+                    moves.swap_remove(index);
+
+                    continue;
+                }
+
+                index += 1;
+            }
+        }
+
+        ::movegen_stats::record(|stats| {
+            stats.rejections += (generated_num - moves.len()) as u64;
+
+            if self.is_in_check() {
+                stats.evasions += moves.len() as u64;
+            }
+        });
+    }
+
+    /// Do a move.
+    ///
+    /// Arguments:
+    /// * `move`: The move to do.
+    /// * `incremental_update`: If false, historical variables (check bitboards, etc...) are not set.
+    pub fn _do_move_with_option(&mut self, m: &Move, incremental_update: bool) {
+        assert!(m.get_capture_piece().get_piece_type() != PieceType::KING);
+
+        let mut hash = self.history.hash[self.ply as usize];
+
+        // HalfKPの特徴量の差分更新
+        self.accumulator = self.accumulator.moved(self, m);
+
+        if m.is_hand() {
+            // 持ち駒を打つ場合
+
+            self.board[m.get_to() as usize] = m.get_piece();
+            self.hand[self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2] -= 1;
+
+            // Bitboardの更新
+            self.piece_bb[m.get_piece().as_usize()] |= 1 << m.get_to();
+            self.player_bb[self.side_to_move.as_usize()] |= 1 << m.get_to();
+
+            // 二歩フラグの更新
+            if m.get_piece().get_piece_type() == PieceType::PAWN {
+                self.pawn_flags[self.side_to_move.as_usize()] |= 1 << (m.get_to() % 5);
+            }
+
+            // hash値の更新
+            hash.0 ^= ::zobrist::BOARD_TABLE[m.get_to()][m.get_piece().as_usize()];
+            hash.1 ^= ::zobrist::HAND_TABLE
+                [self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2]
+                [self.hand[self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2]
+                    as usize
+                    + 1];
+            hash.1 ^= ::zobrist::HAND_TABLE
+                [self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2]
+                [self.hand[self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2]
+                    as usize];
+        } else {
+            // 盤上の駒を動かす場合
+
+            if m.get_capture_piece() != Piece::NO_PIECE {
+                self.hand[self.side_to_move.as_usize()]
+                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2] += 1;
+
+                // Bitboardの更新
+                self.piece_bb[m.get_capture_piece().as_usize()] ^= 1 << m.get_to();
+                self.player_bb[self.side_to_move.get_op_color().as_usize()] ^= 1 << m.get_to();
+
+                // 二歩フラグの更新
+                if m.get_capture_piece().get_piece_type() == PieceType::PAWN {
+                    self.pawn_flags[self.side_to_move.get_op_color().as_usize()] ^= 1 << (m.get_to() % 5);
+                }
+
+                // hashの更新
+                hash.0 ^= ::zobrist::BOARD_TABLE[m.get_to()][m.get_capture_piece().as_usize()];
+                hash.1 ^= ::zobrist::HAND_TABLE
+                    [self.side_to_move.as_usize()]
+                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2][self
+                    .hand[self.side_to_move.as_usize()]
+                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2]
+                    as usize
+                    - 1];
+                hash.1 ^= ::zobrist::HAND_TABLE
+                    [self.side_to_move.as_usize()]
+                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2][self
+                    .hand[self.side_to_move.as_usize()]
+                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2]
+                    as usize];
+            }
+
+            if m.is_promotion() {
+                self.board[m.get_to() as usize] = m.get_piece().get_promoted();
+
+                // 二歩フラグの更新
+                if m.get_piece().get_piece_type() == PieceType::PAWN {
+                    self.pawn_flags[self.side_to_move.as_usize()] ^= 1 << (m.get_to() % 5);
+                }
+            } else {
+                self.board[m.get_to() as usize] = m.get_piece();
+            }
+
+            self.board[m.get_from() as usize] = Piece::NO_PIECE;
+
+            // Bitboardの更新
+            // 移動先
+            self.piece_bb[self.board[m.get_to() as usize].as_usize()] |= 1 << m.get_to();
+            self.player_bb[self.side_to_move.as_usize()] |= 1 << m.get_to();
+            // 移動元
+            self.piece_bb[m.get_piece().as_usize()] ^= 1 << m.get_from();
+            self.player_bb[self.side_to_move.as_usize()] ^= 1 << m.get_from();
+
+            // hash値の更新
+            hash.0 ^= ::zobrist::BOARD_TABLE[m.get_from()][m.get_piece().as_usize()];
+            hash.0 ^= ::zobrist::BOARD_TABLE[m.get_to()][self.board[m.get_to()].as_usize()];
+        }
+
+        hash.0 ^= 1; // 手番bitの反転
+
+        // 1手進める
+        self.ply += 1;
+
+        // 手番を変える
+        self.side_to_move = self.side_to_move.get_op_color();
+
+        // 王手している駒
+        let (adjacent_check_bb, long_check_bb) =
+            if incremental_update { self.calculate_check_bb() } else { (0, 0) };
+
+        let history = Arc::make_mut(&mut self.history);
+
+        // 棋譜に登録
+        history.kif.push(*m);
+        history.hash.push(hash);
+        history.adjacent_check_bb.push(adjacent_check_bb);
+        history.long_check_bb.push(long_check_bb);
+        history.sequent_check_count.push([0; 2]);
+
+        if incremental_update {
+            // 連続王手のカウント
+            if history.adjacent_check_bb[self.ply as usize] != 0
+                || history.long_check_bb[self.ply as usize] != 0
+            {
+                history.sequent_check_count[self.ply as usize]
+                    [self.side_to_move.get_op_color().as_usize()] = history.sequent_check_count
+                    [self.ply as usize - 1][self.side_to_move.get_op_color().as_usize()]
+                    + 1;
+            } else {
+                history.sequent_check_count[self.ply as usize]
+                    [self.side_to_move.get_op_color().as_usize()] = 0;
+            }
+            history.sequent_check_count[self.ply as usize][self.side_to_move.as_usize()] =
+                history.sequent_check_count[self.ply as usize - 1][self.side_to_move.as_usize()];
+        }
+    }
+
+    /// Do a move.
+    pub fn do_move(&mut self, m: &Move) {
+        self._do_move_with_option(m, true);
+    }
+
+    /// Undo the move.
+    pub fn undo_move(&mut self) {
+        assert!(self.ply > 0);
+
+        let history = Arc::make_mut(&mut self.history);
+
+        // 手数を戻す
+        let m = history.kif.pop().unwrap();
+        self.ply -= 1;
+
+        history.hash.truncate(self.ply as usize + 1);
+        history.adjacent_check_bb.truncate(self.ply as usize + 1);
+        history.long_check_bb.truncate(self.ply as usize + 1);
+        history.sequent_check_count.truncate(self.ply as usize + 1);
+
+        // 手番を戻す
+        self.side_to_move = self.side_to_move.get_op_color();
+
+        if m.is_hand() {
+            // 持ち駒を打った場合
+
+            self.board[m.get_to() as usize] = Piece::NO_PIECE;
+            self.hand[self.side_to_move.as_usize()][m.get_piece().get_piece_type().as_usize() - 2] += 1;
+
+            // Bitboardのundo
+            self.piece_bb[m.get_piece().as_usize()] ^= 1 << m.get_to();
+            self.player_bb[self.side_to_move.as_usize()] ^= 1 << m.get_to();
+
+            // 二歩フラグのundo
+            if m.get_piece().get_piece_type() == PieceType::PAWN {
+                self.pawn_flags[self.side_to_move.as_usize()] ^= 1 << (m.get_to() % 5);
+            }
+        } else {
+            // 盤上の駒を動かした場合
+            assert!(self.board[m.get_to() as usize] != Piece::NO_PIECE);
 
-                            // 飛車による王手
-                            let rook_check_bb = rook_attack(king_square, player_bb) & !(1 << m.get_to());
+            // Bitboardのundo
+            // 移動先
+            self.piece_bb[self.board[m.get_to() as usize].as_usize()] ^= 1 << m.get_to();
+            self.player_bb[self.side_to_move.as_usize()] ^= 1 << m.get_to();
+            // 移動元
+            self.piece_bb[m.get_piece().as_usize()] |= 1 << m.get_from();
+            self.player_bb[self.side_to_move.as_usize()] |= 1 << m.get_from();
 
-                            if rook_check_bb
-                                & self.piece_bb[PieceType::ROOK
-                                    .get_piece(self.side_to_move.get_op_color())
-                                    .as_usize()]
-                                != 0
-                                || rook_check_bb
-                                    & self.piece_bb[PieceType::ROOK_X
-                                        .get_piece(self.side_to_move.get_op_color())
-                                        .as_usize()]
-                                    != 0
-                            {
-                                return false;
-                            }
-                        }
-                    }
+            // 二歩フラグのundo
+            if m.get_piece().get_piece_type() == PieceType::PAWN && m.is_promotion() {
+                self.pawn_flags[self.side_to_move.as_usize()] |= 1 << (m.get_to() % 5);
+            }
 
-                    return true;
-                }(moves[index]);
+            self.board[m.get_to() as usize] = m.get_capture_piece();
+            self.board[m.get_from() as usize] = m.get_piece();
 
-                if !is_legal {
-                    moves.swap_remove(index);
+            // 相手の駒を取っていた場合には、持ち駒から減らす
+            if m.get_capture_piece() != Piece::NO_PIECE {
+                self.hand[self.side_to_move.as_usize()]
+                    [m.get_capture_piece().get_piece_type().get_raw().as_usize() - 2] -= 1;
 
-                    continue;
-                }
+                // Bitboardのundo
+                self.piece_bb[m.get_capture_piece().as_usize()] |= 1 << m.get_to();
+                self.player_bb[self.side_to_move.get_op_color().as_usize()] |= 1 << m.get_to();
 
-                index += 1;
+                // 二歩フラグのundo
+                if m.get_capture_piece().get_piece_type() == PieceType::PAWN {
+                    self.pawn_flags[self.side_to_move.get_op_color().as_usize()] |= 1 << (m.get_to() % 5);
+                }
             }
         }
 
-        return moves;
+        // HalfKPの特徴量のundo
+        self.accumulator = self.accumulator.unmoved(self, &m);
     }
 }
 
@@ -1343,6 +2334,50 @@ fn char_to_piece(c: char) -> Piece {
     }
 }
 
+/// Convert the color (0: WHITE, 1: BLACK) given from Python.
+fn check_color(color: u8) -> Result<Color, PositionError> {
+    if color > 1 {
+        return Err(PositionError(format!("invalid color {}", color)));
+    }
+
+    Ok(Color(color))
+}
+
+/// Return the sign of the player in CSA, where `+` is WHITE (sente).
+fn csa_sign(color: Color) -> &'static str {
+    if color == Color::WHITE {
+        "+"
+    } else {
+        "-"
+    }
+}
+
+fn csa_color(sign: &str) -> Color {
+    if sign == "+" {
+        Color::WHITE
+    } else {
+        Color::BLACK
+    }
+}
+
+fn csa_to_piece_type(csa: &str) -> PieceType {
+    match PIECE_TYPE_ALL.iter().find(|piece_type| CSA_PIECE[piece_type.as_usize()] == csa) {
+        Some(piece_type) => *piece_type,
+        None => PieceType::NO_PIECE_TYPE,
+    }
+}
+
+/// Convert a CSA represented square such as `55` (the square `5e`).
+fn csa_to_square(csa: &str) -> Option<usize> {
+    let bytes = csa.as_bytes();
+
+    if bytes.len() == 2 && (b'1'..=b'5').contains(&bytes[0]) && (b'1'..=b'5').contains(&bytes[1]) {
+        Some(::square::make_square((bytes[0] - b'0') as usize, (bytes[1] - b'0') as usize))
+    } else {
+        None
+    }
+}
+
 fn piece_to_string(piece: Piece) -> String {
     match piece {
         Piece::W_KING => "K".to_string(),
@@ -1448,7 +2483,7 @@ fn move_do_undo_test() {
             let moves = position.generate_moves();
 
             for m in &moves {
-                let mut temp_position = position;
+                let mut temp_position = position.clone();
 
                 if m.get_capture_piece().get_piece_type() == PieceType::KING {
                     continue;
@@ -1482,21 +2517,21 @@ fn move_do_undo_test() {
                 assert_eq!(position.ply, temp_position.ply);
 
                 for i in 0..position.ply as usize {
-                    assert!(position.kif[i] == temp_position.kif[i]);
+                    assert!(position.history.kif[i] == temp_position.history.kif[i]);
                 }
 
                 assert_eq!(position.get_hash(), temp_position.get_hash());
 
                 for i in 0..position.ply as usize {
-                    assert_eq!(position.adjacent_check_bb[i], temp_position.adjacent_check_bb[i]);
-                    assert_eq!(position.long_check_bb[i], temp_position.long_check_bb[i]);
+                    assert_eq!(position.history.adjacent_check_bb[i], temp_position.history.adjacent_check_bb[i]);
+                    assert_eq!(position.history.long_check_bb[i], temp_position.history.long_check_bb[i]);
                 }
 
                 for i in 0..position.ply as usize {
                     for j in 0..2 {
                         assert_eq!(
-                            position.sequent_check_count[i][j],
-                            temp_position.sequent_check_count[i][j]
+                            position.history.sequent_check_count[i][j],
+                            temp_position.history.sequent_check_count[i][j]
                         );
                     }
                 }
@@ -1529,7 +2564,7 @@ fn sfen_test() {
 
             {
                 let mut temp_position = Position::empty_board();
-                temp_position.set_sfen(&position.sfen(true));
+                temp_position.set_sfen(&position.sfen(true)).unwrap();
 
                 assert_eq!(position.side_to_move, temp_position.side_to_move);
                 for i in 0..SQUARE_NB {
@@ -1555,21 +2590,21 @@ fn sfen_test() {
                 assert_eq!(position.ply, temp_position.ply);
 
                 for i in 0..position.ply as usize {
-                    assert!(position.kif[i] == temp_position.kif[i]);
+                    assert!(position.history.kif[i] == temp_position.history.kif[i]);
                 }
 
                 assert_eq!(position.get_hash(), temp_position.get_hash());
 
                 for i in 0..position.ply as usize {
-                    assert_eq!(position.adjacent_check_bb[i], temp_position.adjacent_check_bb[i]);
-                    assert_eq!(position.long_check_bb[i], temp_position.long_check_bb[i]);
+                    assert_eq!(position.history.adjacent_check_bb[i], temp_position.history.adjacent_check_bb[i]);
+                    assert_eq!(position.history.long_check_bb[i], temp_position.history.long_check_bb[i]);
                 }
 
                 for i in 0..position.ply as usize {
                     for j in 0..2 {
                         assert_eq!(
-                            position.sequent_check_count[i][j],
-                            temp_position.sequent_check_count[i][j]
+                            position.history.sequent_check_count[i][j],
+                            temp_position.history.sequent_check_count[i][j]
                         );
                     }
                 }
@@ -1577,7 +2612,7 @@ fn sfen_test() {
 
             {
                 let mut temp_position = Position::empty_board();
-                temp_position.set_sfen(&position.sfen(false));
+                temp_position.set_sfen(&position.sfen(false)).unwrap();
 
                 assert_eq!(position.side_to_move, temp_position.side_to_move);
                 for i in 0..SQUARE_NB {
@@ -1659,44 +2694,84 @@ fn no_legal_move_test() {
 
     let mut position = Position::empty_board();
 
-    position.set_sfen(CHECKMATE_SFEN1);
+    position.set_sfen(CHECKMATE_SFEN1).unwrap();
+    assert_eq!(position.generate_moves().len(), 0);
+
+    position.set_sfen(CHECKMATE_SFEN2).unwrap();
+    assert_eq!(position.generate_moves().len(), 0);
+
+    position.set_sfen(CHECKMATE_SFEN3).unwrap();
     assert_eq!(position.generate_moves().len(), 0);
 
-    position.set_sfen(CHECKMATE_SFEN2);
+    position.set_sfen(CHECKMATE_SFEN4).unwrap();
     assert_eq!(position.generate_moves().len(), 0);
 
-    position.set_sfen(CHECKMATE_SFEN3);
+    position.set_sfen(CHECKMATE_SFEN5).unwrap();
     assert_eq!(position.generate_moves().len(), 0);
 
-    position.set_sfen(CHECKMATE_SFEN4);
+    position.set_sfen(CHECKMATE_SFEN6).unwrap();
     assert_eq!(position.generate_moves().len(), 0);
 
-    position.set_sfen(CHECKMATE_SFEN5);
+    position.set_sfen(CHECKMATE_SFEN7).unwrap();
     assert_eq!(position.generate_moves().len(), 0);
 
-    position.set_sfen(CHECKMATE_SFEN6);
+    position.set_sfen(CHECKMATE_SFEN8).unwrap();
     assert_eq!(position.generate_moves().len(), 0);
 
-    position.set_sfen(CHECKMATE_SFEN7);
+    position.set_sfen(CHECKMATE_SFEN9).unwrap();
     assert_eq!(position.generate_moves().len(), 0);
 
-    position.set_sfen(CHECKMATE_SFEN8);
+    position.set_sfen(CHECKMATE_SFEN10).unwrap();
     assert_eq!(position.generate_moves().len(), 0);
+}
+
+#[test]
+fn missing_king_test() {
+    let mut position = Position::empty_board();
+
+    // 攻め方の玉がない詰将棋の局面
+    position.set_sfen("4k/5/4P/5/5 b G 1").unwrap();
+    assert!(!position.is_in_check());
+    assert!(!position.generate_moves_with_option(false, true, false, true).is_empty());
+    assert!(position.get_halfkp_features(0).is_err());
+    assert!(position.get_halfkp_features(1).is_ok());
 
-    position.set_sfen(CHECKMATE_SFEN9);
+    let m = position.sfen_to_move("G*1b").unwrap();
+    position.do_move(&m);
+    assert!(position.is_in_check());
     assert_eq!(position.generate_moves().len(), 0);
 
-    position.set_sfen(CHECKMATE_SFEN10);
+    position.undo_move();
+    assert!(position.set_sfen("4k/5/4P/5/5 b G 1 moves G*2a 1a2a").is_ok());
+
+    // 相手の玉がなければ、王手となる持ち駒の打ち方もない
+    position.set_sfen("5/5/5/5/4k w g 1").unwrap();
+    assert!(!position.generate_moves().is_empty());
+    assert_eq!(position.generate_moves_with_option(false, true, false, true).len(), 0);
+
+    position.set_sfen("5/5/5/5/5 b - 1").unwrap();
     assert_eq!(position.generate_moves().len(), 0);
 }
 
+#[test]
+fn hand_full_test() {
+    let mut position = Position::empty_board();
+
+    // 金が3枚以上ある局面では、持ち駒が3枚になるように金を取れない
+    position.set_sfen("4k/5/5/1gg2/K1G2 b 2G 1").unwrap();
+
+    let m = Move::board_move(Piece::W_GOLD, 22, 17, false, Piece::B_GOLD);
+    assert!(position.check_move(&m).is_err());
+    assert!(!position.generate_moves().iter().any(|m| m.get_capture_piece() != Piece::NO_PIECE));
+}
+
 #[test]
 fn not_checkmate_positions() {
     static NOT_CHECKMATE_SFEN1: &str = "rb1gk/1s2R/5/P1B2/KGS2 w P 1";
 
     let mut position = Position::empty_board();
 
-    position.set_sfen(NOT_CHECKMATE_SFEN1);
+    position.set_sfen(NOT_CHECKMATE_SFEN1).unwrap();
     assert!(position.generate_moves().len() > 0);
 }
 
@@ -1739,6 +2814,8 @@ fn generate_moves_test() {
 
     let mut rng = rand::thread_rng();
 
+    let mut buffer = std::vec::Vec::new();
+
     for _ in 0..LOOP_NUM {
         position.set_start_position();
 
@@ -1746,6 +2823,10 @@ fn generate_moves_test() {
             let moves = position.generate_moves();
             let allow_illegal_moves = position.generate_moves_with_option(true, true, true, false);
 
+            // 使い回したバッファにも同じ指し手が生成される
+            position.generate_moves_into(&mut buffer);
+            assert!(buffer == moves);
+
             let mut legal_move_count = allow_illegal_moves.len();
             for m in allow_illegal_moves {
                 position.do_move(&m);
@@ -1820,31 +2901,65 @@ fn is_repetition_test() {
     static NOT_CHECK_REPETITION_SFEN: &str =
         "2k2/5/5/5/2K2 b R 1 moves R*3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a";
 
-    position.set_sfen(START_POSITION_SFEN);
+    position.set_sfen(START_POSITION_SFEN).unwrap();
     assert_eq!(position.is_repetition(), (false, false, false));
 
-    position.set_sfen(REPETITION_SFEN);
+    position.set_sfen(REPETITION_SFEN).unwrap();
     assert_eq!(position.is_repetition(), (true, false, false));
 
-    position.set_sfen(REPETITION_SFEN2);
+    position.set_sfen(REPETITION_SFEN2).unwrap();
     assert_eq!(position.is_repetition(), (true, false, false));
 
-    position.set_sfen(CHECK_REPETITION_SFEN);
+    position.set_sfen(CHECK_REPETITION_SFEN).unwrap();
     assert_eq!(position.is_repetition(), (true, false, true));
 
-    position.set_sfen(CHECK_REPETITION_SFEN2);
+    position.set_sfen(CHECK_REPETITION_SFEN2).unwrap();
     assert_eq!(position.is_repetition(), (true, false, true));
 
-    position.set_sfen(CHECK_REPETITION_SFEN3);
+    position.set_sfen(CHECK_REPETITION_SFEN3).unwrap();
     assert_eq!(position.is_repetition(), (true, true, false));
 
-    position.set_sfen(NOT_REPETITION_SFEN);
+    position.set_sfen(NOT_REPETITION_SFEN).unwrap();
     assert_eq!(position.is_repetition(), (false, false, false));
 
-    position.set_sfen(NOT_CHECK_REPETITION_SFEN);
+    position.set_sfen(NOT_CHECK_REPETITION_SFEN).unwrap();
     assert_eq!(position.is_repetition(), (false, false, false));
 }
 
+#[test]
+fn get_repetition_state_test() {
+    let mut position = Position::empty_board();
+
+    static REPETITION_SFEN: &str = "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e 2b1a";
+    static CHECK_REPETITION_SFEN: &str = "2k2/5/5/5/2K2 b R 1 moves R*3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a 2c3c";
+    static CHECK_REPETITION_SFEN3: &str =
+        "3k1/5/2R2/5/2K2 b - 1 moves 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a 2c3c 3a2a";
+    static NOT_REPETITION_SFEN: &str =
+        "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e 2b1a";
+    static MIXED_CHECK_REPETITION_SFEN: &str =
+        "4k/5/5/5/K4 b R 1 moves R*1c 1a2a 1c3c 2a1a 3c1c 1a2a 1c3c 2a1a 3c1c 1a2a 1c2c 2a1a 2c1c";
+
+    position.set_start_position();
+    assert_eq!(position.get_repetition_state(), Repetition::NONE);
+
+    position.set_sfen(REPETITION_SFEN).unwrap();
+    assert_eq!(position.get_repetition_state(), Repetition::DRAW);
+
+    position.set_sfen(CHECK_REPETITION_SFEN).unwrap();
+    assert_eq!(position.get_repetition_state(), Repetition::WIN);
+
+    position.set_sfen(CHECK_REPETITION_SFEN3).unwrap();
+    assert_eq!(position.get_repetition_state(), Repetition::LOSE);
+
+    position.set_sfen(NOT_REPETITION_SFEN).unwrap();
+    assert_eq!(position.get_repetition_state(), Repetition::NONE);
+
+    // 直前の1周だけが連続王手で、それより前に王手でない手が含まれている場合は通常の千日手
+    position.set_sfen(MIXED_CHECK_REPETITION_SFEN).unwrap();
+    assert_eq!(position.is_repetition(), (true, false, true));
+    assert_eq!(position.get_repetition_state(), Repetition::DRAW);
+}
+
 #[test]
 fn get_repetition_test() {
     let mut position = Position::empty_board();
@@ -1857,22 +2972,47 @@ fn get_repetition_test() {
     static NOT_CHECK_REPETITION_SFEN: &str =
         "2k2/5/5/5/2K2 b R 1 moves R*3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a";
 
-    position.set_sfen(START_POSITION_SFEN);
+    position.set_sfen(START_POSITION_SFEN).unwrap();
     assert_eq!(position.get_repetition(), 0);
 
-    position.set_sfen(REPETITION_SFEN);
+    position.set_sfen(REPETITION_SFEN).unwrap();
     assert_eq!(position.get_repetition(), 3);
 
-    position.set_sfen(CHECK_REPETITION_SFEN);
+    position.set_sfen(CHECK_REPETITION_SFEN).unwrap();
     assert_eq!(position.get_repetition(), 3);
 
-    position.set_sfen(NOT_REPETITION_SFEN);
+    position.set_sfen(NOT_REPETITION_SFEN).unwrap();
     assert_eq!(position.get_repetition(), 2);
 
-    position.set_sfen(NOT_CHECK_REPETITION_SFEN);
+    position.set_sfen(NOT_CHECK_REPETITION_SFEN).unwrap();
     assert_eq!(position.get_repetition(), 2);
 }
 
+#[test]
+fn occurrences_test() {
+    let mut position = Position::empty_board();
+
+    position.set_start_position();
+    assert_eq!(position.occurrences(), 1);
+
+    let moves = ["5e4d", "1a2b", "4d5e", "2b1a"];
+
+    // 同じ局面に戻るたびに 1 ずつ増え、4 回目で千日手になる
+    for expected in 2..5 {
+        for m in moves.iter() {
+            let m = position.sfen_to_move(m).unwrap();
+            position.do_move(&m);
+        }
+
+        assert_eq!(position.occurrences(), expected);
+        assert_eq!(position.is_repetition().0, expected == 4);
+    }
+
+    // 1 手戻した局面は 3 回目
+    position.undo_move();
+    assert_eq!(position.occurrences(), 3);
+}
+
 #[test]
 fn sfen_to_move_test() {
     const LOOP_NUM: i32 = 10000;
@@ -1892,7 +3032,7 @@ fn sfen_to_move_test() {
             }
 
             for m in &moves {
-                let sfen_move = position.sfen_to_move(&m.sfen());
+                let sfen_move = position.sfen_to_move(&m.sfen()).unwrap();
                 assert_eq!(sfen_move, *m);
             }
 
@@ -1902,6 +3042,348 @@ fn sfen_to_move_test() {
     }
 }
 
+#[test]
+fn usi_sfen_test() {
+    let mut position = Position::empty_board();
+    let mut expected = Position::empty_board();
+
+    expected.set_sfen_without_startpos("5e4d 1a2b").unwrap();
+
+    // USIの `position` コマンドの引数の形式
+    for sfen in [
+        "startpos moves 5e4d 1a2b",
+        "sfen rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b",
+        "rbsgk/4p/5/P4/KGSBR b - moves 5e4d 1a2b",
+    ]
+    .iter()
+    {
+        position.set_sfen(sfen).unwrap();
+
+        assert_eq!(position.sfen(true), expected.sfen(true));
+        assert_eq!(position.get_hash(), expected.get_hash());
+    }
+
+    position.set_sfen("startpos").unwrap();
+    assert_eq!(position.get_ply(), 0);
+    assert_eq!(position.get_move_number(), 1);
+
+    // 手数は途中局面から再開した場合にも保持される
+    position.set_sfen("4k/5/5/5/K4 w - 12 moves 1a2a").unwrap();
+    assert_eq!(position.get_ply(), 1);
+    assert_eq!(position.get_move_number(), 13);
+    assert_eq!(position.copy(false).get_move_number(), 13);
+
+    // 最大手数まで進めても u16 に収まる手数だけを受け付ける
+    let max_move_number = u16::MAX - MAX_PLY as u16;
+    position.set_sfen(&format!("4k/5/5/5/K4 w - {} moves 1a2a", max_move_number)).unwrap();
+    assert_eq!(position.get_move_number(), max_move_number + 1);
+    assert!(position.set_sfen(&format!("4k/5/5/5/K4 w - {}", max_move_number + 1)).is_err());
+    assert!(position.set_sfen("4k/5/5/5/K4 w - 65535").is_err());
+}
+
+#[test]
+fn sfen_export_test() {
+    let mut position = Position::empty_board();
+
+    position.set_start_position();
+    assert_eq!(position.usi_position(), "startpos");
+
+    position.set_sfen("startpos moves 5e4d 1a2b").unwrap();
+    assert_eq!(position.sfen(true), "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b");
+    assert_eq!(position.sfen(false), "rbsg1/3kp/5/PK3/1GSBR b - 3");
+    assert_eq!(position.usi_position(), "startpos moves 5e4d 1a2b");
+
+    // 持ち駒は先手、後手の順に飛、角、金、銀、歩の順
+    position.set_sfen("2k2/5/5/5/2K2 w P2SGr2b 7 moves 3a2a").unwrap();
+    assert_eq!(position.sfen(false), "3k1/5/5/5/2K2 b G2SPr2b 8");
+    assert_eq!(position.usi_position(), "sfen 2k2/5/5/5/2K2 w G2SPr2b 7 moves 3a2a");
+
+    // 出力した sfen を読み込むと同じ局面になる
+    let mut parsed = Position::empty_board();
+    parsed.set_sfen(&position.usi_position()).unwrap();
+    assert_eq!(parsed.sfen(false), position.sfen(false));
+    assert_eq!(parsed.get_hash(), position.get_hash());
+}
+
+#[test]
+fn csa_test() {
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    static START_POSITION_CSA: &str = "P1-HI-KA-GI-KI-OU\n\
+                                       P2 *  *  *  * -FU\n\
+                                       P3 *  *  *  *  * \n\
+                                       P4+FU *  *  *  * \n\
+                                       P5+OU+KI+GI+KA+HI\n\
+                                       +\n";
+
+    assert_eq!(position.csa(true), START_POSITION_CSA);
+
+    // コメントや対局情報は読み飛ばし、末尾の空白の省略や `,` 区切りも受け付ける
+    let csa = "V2.2\nN+sente\nN-gote\n'comment\n\
+               P1-HI-KA-GI-KI-OU\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\nP5+OU+KI+GI+KA+HI\n\
+               +\n+5544OU,T3\n-1122OU\n+1514HI\n%TORYO\n";
+    position.set_csa(csa).unwrap();
+    assert_eq!(position.sfen(true), "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b 1e1d");
+
+    // 持ち駒と成る手
+    position.set_csa("P1 *  *  * -OU * \nP2+FU *  *  *  * \nP3 *  *  *  *  *\nP4 *  *  *  *  *\nP5+OU *  *  *  *\nP+00KI00FU\nP-00HI\n+\n+5251TO\n").unwrap();
+    assert_eq!(position.sfen(false), "+P2k1/5/5/5/K4 w GPr 2");
+    assert_eq!(position.csa(false), "P1+TO *  * -OU * \nP2 *  *  *  *  * \nP3 *  *  *  *  * \nP4 *  *  *  *  * \nP5+OU *  *  *  * \nP+00KI00FU\nP-00HI\n-\n");
+
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..100 {
+        position.set_start_position();
+
+        while position.ply < MAX_PLY as u16 {
+            let moves = position.generate_moves();
+
+            // 指し手の CSA 表記は元の指し手に戻せる
+            for m in &moves {
+                assert!(position.csa_to_move(&m.csa()).unwrap() == *m);
+            }
+
+            if moves.is_empty() {
+                break;
+            }
+            let random_move = moves.choose(&mut rng).unwrap();
+            position.do_move(random_move);
+        }
+
+        let mut parsed = Position::empty_board();
+        parsed.set_csa(&position.csa(true)).unwrap();
+        assert_eq!(parsed.sfen(true), position.sfen(true));
+
+        // 局面だけの CSA には手数が含まれない
+        parsed.set_csa(&position.csa(false)).unwrap();
+        assert_eq!(parsed.get_hash(), position.get_hash());
+    }
+
+    position.set_start_position();
+
+    static INVALID_CSAS: [&str; 7] = [
+        "P1-HI-KA-GI-KI-OU\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\n+\n",
+        "P1-HI-KA-GI-KI-OU\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\nP5+OU+KI+GI+KA+HI\n",
+        "P1-HI-KA-GI-KI-XX\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\nP5+OU+KI+GI+KA+HI\n+\n",
+        "P1-HI-KA-GI-KI-OU\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\nP5+OU+KI+GI+KA+HI\n+\n-1122OU\n",
+        "P1-HI-KA-GI-KI-OU\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\nP5+OU+KI+GI+KA+HI\n+\n+5554KI\n",
+        "P1-HI-KA-GI-KI-OU\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\nP5+OU+KI+GI+KA+HI\nP+00OU\n+\n",
+        "P1-HI-KA-GI-KI-OU\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\nP5+OU+KI+GI+KA+HI\nP+00FU00FU00FU\n+\n",
+    ];
+
+    for csa in INVALID_CSAS.iter() {
+        assert!(position.set_csa(csa).is_err());
+
+        // 失敗した場合には局面は変わらない
+        assert_eq!(position.csa(true), START_POSITION_CSA);
+    }
+
+    // 持ち駒の数がu8に収まらない場合もエラーになる
+    let many_pawns = format!(
+        "P1-HI-KA-GI-KI-OU\nP2 *  *  *  * -FU\nP3 *  *  *  *  *\nP4+FU *  *  *  *\nP5+OU+KI+GI+KA+HI\nP+{}\n+\n",
+        "00FU".repeat(256)
+    );
+    assert!(position.set_csa(&many_pawns).is_err());
+
+    assert!(position.csa_to_move("+5544OU").is_ok());
+    assert!(position.csa_to_move("5544OU").is_ok());
+    assert!(position.csa_to_move("-5544OU").is_err());
+    assert!(position.csa_to_move("+5544GI").is_err());
+    assert!(position.csa_to_move("0033OU").is_err());
+    assert!(position.csa_to_move("+5544").is_err());
+}
+
+#[test]
+fn invalid_sfen_test() {
+    static START_POSITION_SFEN: &str = "rbsgk/4p/5/P4/KGSBR b - 1";
+    static INVALID_SFENS: [&str; 22] = [
+        "",
+        "rbsgk/4p/5/P4 b - 1",
+        "rbsgk/4p/6/P4/KGSBR b - 1",
+        "rbsgk/4p/5/P4/KGSBX b - 1",
+        "rbsgk/4p/5/+P+4/KGSBR b - 1",
+        "rbsgk/4p/5/P4/KGSBR x - 1",
+        "rbsgk/4p/5/P4/KGSBR b K 1",
+        "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e5f",
+        "rbsgk/4p/5/P4/KGSBR b - 1 moves 4e4d 4e4d",
+        "rbsgk/4p/5/P4/KGSBR b - 1 moves G*3c",
+        "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e5d",
+        "rbsgk/4p/5/P4/KGSBR b - 0",
+        "rbsgk/4p/5/P4/KGSBR b - x moves 5e4d",
+        "rbsgk/4p/5/P4/KGSBR b - 1 5e4d",
+        "startpos 5e4d",
+        "4k/5/5/5/K4 b 3P 1",
+        "4k/5/5/5/K4 b 12P 1",
+        "4k/5/5/5/K4 b 0P 1",
+        "4k/5/5/5/K4 b 99999P 1",
+        "4k/5/5/5/K4 b P2 1",
+        "4k/5/5/5/K4 b PPP 1",
+        "4k/5/5/5/KK3 b - 1",
+    ];
+
+    let mut position = Position::empty_board();
+    position.set_start_position();
+
+    for sfen in INVALID_SFENS.iter() {
+        assert!(position.set_sfen(sfen).is_err());
+
+        // 失敗した場合には局面は変わらない
+        assert_eq!(position.sfen(true), START_POSITION_SFEN);
+    }
+
+    assert!(position.sfen_to_move("5e").is_err());
+    assert!(position.sfen_to_move("K*3c").is_err());
+    assert!(position.sfen_to_move("5e5d=").is_err());
+    assert!(position.sfen_to_move("5e4d").is_ok());
+
+    // 指せない手はエラーになり、局面は変わらない
+    assert!(position.py_do_move_with_option(&Move::hand_move(Piece::W_GOLD, 12), false).is_err());
+    assert_eq!(position.sfen(true), START_POSITION_SFEN);
+
+    let m = position.sfen_to_move("5e4d").unwrap();
+    assert!(position.py_do_move_with_option(&m, true).is_ok());
+    assert_eq!(position.sfen(true), "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d");
+
+    // 持ち駒の枚数
+    position.set_sfen("4k/5/5/5/K4 b 2Pgs 1").unwrap();
+    assert_eq!(position.hand, [[0, 0, 0, 0, 2], [1, 1, 0, 0, 0]]);
+    assert_eq!(position.sfen(true), "4k/5/5/5/K4 b 2Pgs 1");
+}
+
+#[test]
+fn attack_counts_test() {
+    let mut position = Position::empty_board();
+    position.set_sfen("4k/5/5/5/K3R b - 1").unwrap();
+
+    #[rustfmt::skip]
+    let expected: [u8; SQUARE_NB] = [
+        0, 0, 0, 0, 1,
+        0, 0, 0, 0, 1,
+        0, 0, 0, 0, 1,
+        1, 1, 0, 0, 1,
+        1, 2, 1, 1, 0,
+    ];
+    assert_eq!(position.attack_counts(Color::WHITE), expected);
+    assert_eq!(position.get_attack_counts(0).unwrap(), expected.to_vec());
+    assert!(position.get_attack_counts(2).is_err());
+
+    // 後手玉の利き
+    let counts = position.attack_counts(Color::BLACK);
+    assert_eq!(counts.iter().map(|x| *x as u32).sum::<u32>(), 3);
+    assert_eq!(counts[3] + counts[8] + counts[9], 3);
+
+    // 初期局面は点対称
+    position.set_start_position();
+    let white_counts = position.attack_counts(Color::WHITE);
+    let black_counts = position.attack_counts(Color::BLACK);
+    for i in 0..SQUARE_NB {
+        assert_eq!(white_counts[i], black_counts[SQUARE_NB - 1 - i]);
+    }
+}
+
+#[test]
+fn pinned_pieces_test() {
+    let mut position = Position::empty_board();
+
+    // 5dの金は5aの飛車にピンされている
+    position.set_sfen("r3k/5/5/G4/K2R1 b - 1").unwrap();
+    assert_eq!(position.pinned_pieces(Color::WHITE), 1 << 15);
+    assert_eq!(position.pinned_pieces(Color::BLACK), 0);
+    assert_eq!(position.discovered_check_candidates(Color::BLACK), 0);
+
+    // 2枚以上の駒が間にある場合はピンではない
+    position.set_sfen("r3k/5/P4/G4/K2R1 b - 1").unwrap();
+    assert_eq!(position.pinned_pieces(Color::WHITE), 0);
+
+    // 2dの銀が動くと1eの角による王手になる
+    position.set_sfen("k4/5/5/3S1/K3B b - 1").unwrap();
+    assert_eq!(position.discovered_check_candidates(Color::WHITE), 1 << 18);
+    assert_eq!(position.pinned_pieces(Color::BLACK), 0);
+    assert_eq!(position.py_discovered_check_candidates(0).unwrap(), vec![18]);
+    assert!(position.py_discovered_check_candidates(2).is_err());
+    assert!(position.py_pinned_pieces(2).is_err());
+
+    // 4bの歩も間にあるので、2dの銀が動いても王手にならない
+    position.set_sfen("k4/1p3/5/3S1/K3B b - 1").unwrap();
+    assert_eq!(position.discovered_check_candidates(Color::WHITE), 0);
+
+    // ピンされている駒は、ピンしている駒との直線上にしか動けない
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..1000 {
+        position.set_start_position();
+
+        while position.ply < MAX_PLY as u16 {
+            let moves = position.generate_moves();
+
+            if moves.is_empty() {
+                break;
+            }
+
+            let color = position.side_to_move;
+            let pinned = position.pinned_pieces(color);
+            let king_square =
+                get_square(position.piece_bb[PieceType::KING.get_piece(color).as_usize()]);
+
+            for m in &moves {
+                if !m.is_hand() && pinned & (1 << m.get_from()) != 0 {
+                    assert!(line_bb(king_square, m.get_from()) & (1 << m.get_to()) != 0);
+                }
+            }
+
+            let random_move = moves.choose(&mut rng).unwrap();
+            position.do_move(random_move);
+        }
+    }
+}
+
+#[test]
+fn try_rule_test() {
+    let mut position = Position::empty_board();
+    position.set_sfen("4k/K4/5/5/5 b - 1 moves 5b5a").unwrap();
+
+    // デフォルトではトライルールは無効
+    assert!(!position.is_try());
+    assert!(!position.generate_moves().is_empty());
+
+    position.set_try_rule(true);
+    assert!(position.is_try());
+    assert_eq!(position.generate_moves().len(), 0);
+
+    // sfenを設定し直してもルールは保持される
+    position.set_sfen("4k/K4/5/5/5 b - 1").unwrap();
+    assert!(position.try_rule);
+    assert!(!position.is_try());
+
+    position.set_sfen("4k/5/5/5/K4 w - 1 moves 1a1b 5e5d 1b1c 5d5c 1c1d 5c5b 1d1e").unwrap();
+    assert!(position.is_try());
+    assert!(position.copy(false).is_try());
+}
+
+#[test]
+fn validate_moves_test() {
+    let to_vec = |moves: &[&str]| moves.iter().map(|x| x.to_string()).collect();
+
+    let mut position = Position::empty_board();
+    position.set_sfen("r3k/5/5/G4/K2R1 b - 1").unwrap();
+
+    assert_eq!(position.validate_moves(to_vec(&["5d5c", "5a5c", "5e4d"])), None);
+
+    // 5dの金はピンされている
+    assert_eq!(position.validate_moves(to_vec(&["5d4d"])).unwrap().0, 0);
+    assert_eq!(position.validate_moves(to_vec(&["5d5c", "5a5c", "5e4d", "3c3d"])).unwrap().0, 3);
+    assert_eq!(position.validate_moves(to_vec(&["5d5c", "5a5c", "5e4d", "xxxx"])).unwrap().0, 3);
+
+    // 打ち歩詰め
+    position.set_sfen("4k/2S2/3G1/5/K4 b P 1").unwrap();
+    assert_eq!(position.validate_moves(to_vec(&["P*1b"])).unwrap().0, 0);
+    assert_eq!(position.validate_moves(to_vec(&["P*1c", "1a2a"])).unwrap().0, 1);
+
+    // 元の局面は変更されない
+    assert_eq!(position.sfen(true), "4k/2S2/3G1/5/K4 b P 1");
+}
+
 #[test]
 fn init_position_moves_test() {
     let mut position = Position::empty_board();
@@ -1929,7 +3411,7 @@ fn do_move_simple_test() {
             simple_position.set_start_position();
 
             for ply in 0..position.ply {
-                simple_position._do_move_with_option(&position.kif[ply as usize], false);
+                simple_position._do_move_with_option(&position.history.kif[ply as usize], false);
             }
 
             simple_position.set_flags();
@@ -1950,8 +3432,8 @@ fn do_move_simple_test() {
                 );
             }
             for i in 0..position.ply as usize {
-                assert_eq!(position.kif[i], simple_position.kif[i]);
-                assert_eq!(position.hash[i], simple_position.hash[i]);
+                assert_eq!(position.history.kif[i], simple_position.history.kif[i]);
+                assert_eq!(position.history.hash[i], simple_position.history.hash[i]);
             }
             assert_eq!(
                 position.pawn_flags[Color::WHITE.as_usize()],
@@ -1973,12 +3455,12 @@ fn do_move_simple_test() {
                 simple_position.player_bb[Color::BLACK.as_usize()]
             );
             assert_eq!(
-                position.adjacent_check_bb[position.ply as usize],
-                simple_position.adjacent_check_bb[position.ply as usize]
+                position.history.adjacent_check_bb[position.ply as usize],
+                simple_position.history.adjacent_check_bb[position.ply as usize]
             );
             assert_eq!(
-                position.long_check_bb[position.ply as usize],
-                simple_position.long_check_bb[position.ply as usize]
+                position.history.long_check_bb[position.ply as usize],
+                simple_position.history.long_check_bb[position.ply as usize]
             );
 
             // ランダムに局面を進める
@@ -2029,3 +3511,28 @@ fn perft() {
     assert_eq!(count_nodes(&mut position, 6), 8276188);
     assert_eq!(count_nodes(&mut position, 7), 132680698);
 }
+
+#[test]
+fn shared_history_test() {
+    let mut position = Position::empty_board();
+    position.set_sfen("rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b").unwrap();
+
+    let hash = position.get_hash();
+
+    // 複製した局面は、どちらかが手を進めるまで履歴を共有する
+    let mut copied = position.clone();
+    assert!(Arc::ptr_eq(&position.history, &copied.history));
+
+    let m = copied.sfen_to_move("4d5e").unwrap();
+    copied.do_move(&m);
+    assert!(!Arc::ptr_eq(&position.history, &copied.history));
+
+    // 元の局面は変わらない
+    assert_eq!(position.history.kif.len(), 2);
+    assert_eq!(position.get_hash(), hash);
+    assert_eq!(position.sfen(true), "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b");
+
+    copied.undo_move();
+    assert_eq!(copied.get_hash(), hash);
+    assert_eq!(copied.sfen(true), position.sfen(true));
+}
@@ -0,0 +1,265 @@
+//! A regression suite of positions with known perft counts and rule cases, which is compiled into the crate
+//! so that the move generator can be checked from Python as well as from `cargo test`.
+
+use pyo3::prelude::*;
+
+use position::*;
+use types::*;
+
+struct PerftCase {
+    name: &'static str,
+    sfen: &'static str,
+    depth: u8,
+    nodes: u64,
+}
+
+struct RuleCase {
+    name: &'static str,
+    sfen: &'static str,
+    /// Space separated moves, which are played from `sfen`.
+    moves: &'static str,
+    legal: bool,
+}
+
+struct RepetitionCase {
+    name: &'static str,
+    sfen: &'static str,
+    expected: Repetition,
+}
+
+static PERFT_CASES: [PerftCase; 5] = [
+    PerftCase {
+        name: "start position",
+        sfen: "rbsgk/4p/5/P4/KGSBR b - 1",
+        depth: 4,
+        nodes: 35401,
+    },
+    PerftCase {
+        name: "double check evasions",
+        sfen: "k4/5/2B2/5/R3K w gs 1",
+        depth: 3,
+        nodes: 678,
+    },
+    PerftCase {
+        name: "drops of all piece types",
+        sfen: "2k2/5/5/5/2K2 b GSBRPgs 1",
+        depth: 3,
+        nodes: 365666,
+    },
+    PerftCase {
+        name: "promotions",
+        sfen: "2k2/P4/1S3/2s1p/K4 b BRgb 1",
+        depth: 3,
+        nodes: 43839,
+    },
+    PerftCase {
+        name: "pawn drop mate candidates",
+        sfen: "3pk/3b1/4G/5/K4 b P 1",
+        depth: 3,
+        nodes: 369,
+    },
+];
+
+static RULE_CASES: [RuleCase; 8] = [
+    RuleCase {
+        name: "checkmate by a pawn drop (uchifuzume)",
+        sfen: "3pk/3b1/4G/5/K4 b P 1",
+        moves: "P*1b",
+        legal: false,
+    },
+    RuleCase {
+        name: "check by a pawn drop which is not a checkmate",
+        sfen: "4k/5/4G/5/K4 b P 1",
+        moves: "P*1b",
+        legal: true,
+    },
+    RuleCase {
+        name: "two pawns on the same file (nifu)",
+        sfen: "2k2/5/5/P4/2K2 b P 1",
+        moves: "P*5c",
+        legal: false,
+    },
+    RuleCase {
+        name: "pawn drop on the last rank",
+        sfen: "2k2/5/5/5/2K2 b P 1",
+        moves: "P*5a",
+        legal: false,
+    },
+    RuleCase {
+        name: "promotion on a drop",
+        sfen: "2k2/5/5/5/2K2 b S 1",
+        moves: "S*3c+",
+        legal: false,
+    },
+    RuleCase {
+        name: "pawn move to the last rank without promotion",
+        sfen: "2k2/P4/5/5/2K2 b - 1",
+        moves: "5b5a",
+        legal: false,
+    },
+    RuleCase {
+        name: "drop while in double check",
+        sfen: "k4/5/2B2/5/R3K w gs 1",
+        moves: "G*5b",
+        legal: false,
+    },
+    RuleCase {
+        name: "king escape from double check",
+        sfen: "k4/5/2B2/5/R3K w gs 1",
+        moves: "5a4a",
+        legal: true,
+    },
+];
+
+static REPETITION_CASES: [RepetitionCase; 3] = [
+    RepetitionCase {
+        name: "repetition",
+        sfen: "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e 2b1a",
+        expected: Repetition::DRAW,
+    },
+    RepetitionCase {
+        name: "repetition with continuous checks",
+        sfen: "2k2/5/5/5/2K2 b R 1 moves R*3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a 2c3c 3a2a 3c2c 2a3a 2c3c",
+        expected: Repetition::WIN,
+    },
+    RepetitionCase {
+        name: "third occurrence",
+        sfen: "rbsgk/4p/5/P4/KGSBR b - 1 moves 5e4d 1a2b 4d5e 2b1a 5e4d 1a2b 4d5e 2b1a",
+        expected: Repetition::NONE,
+    },
+];
+
+/// Count the leaf nodes of the legal move tree of `depth`.
+pub fn perft(position: &mut Position, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = position.generate_moves();
+
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut count = 0;
+
+    for m in &moves {
+        position.do_move(m);
+        count += perft(position, depth - 1);
+        position.undo_move();
+    }
+
+    count
+}
+
+fn set_position(sfen: &str) -> Result<Position, String> {
+    let mut position = Position::empty_board();
+    position.set_sfen(sfen).map_err(|error| error.0)?;
+
+    Ok(position)
+}
+
+/// Run all cases of the regression suite.
+///
+/// Returns the descriptions of the failed cases, which is empty if all cases pass.
+pub fn run_regression() -> std::vec::Vec<String> {
+    let mut failures = std::vec::Vec::new();
+
+    for case in PERFT_CASES.iter() {
+        match set_position(case.sfen) {
+            Ok(mut position) => {
+                let nodes = perft(&mut position, case.depth);
+
+                if nodes != case.nodes {
+                    failures.push(format!(
+                        "perft `{}`: expected {} nodes at depth {}, but got {}",
+                        case.name, case.nodes, case.depth, nodes
+                    ));
+                }
+            }
+            Err(error) => failures.push(format!("perft `{}`: {}", case.name, error)),
+        }
+    }
+
+    for case in RULE_CASES.iter() {
+        match set_position(case.sfen) {
+            Ok(position) => {
+                let moves = case.moves.split_whitespace().map(|m| m.to_string()).collect();
+                let invalid = position.validate_moves(moves);
+
+                if invalid.is_none() != case.legal {
+                    failures.push(format!(
+                        "rule `{}`: expected `{}` to be {}, but got {:?}",
+                        case.name,
+                        case.moves,
+                        if case.legal { "legal" } else { "illegal" },
+                        invalid
+                    ));
+                }
+            }
+            Err(error) => failures.push(format!("rule `{}`: {}", case.name, error)),
+        }
+    }
+
+    for case in REPETITION_CASES.iter() {
+        match set_position(case.sfen) {
+            Ok(position) => {
+                let state = position.get_repetition_state();
+
+                if state != case.expected {
+                    failures.push(format!(
+                        "repetition `{}`: expected {}, but got {}",
+                        case.name, case.expected.0, state.0
+                    ));
+                }
+            }
+            Err(error) => failures.push(format!("repetition `{}`: {}", case.name, error)),
+        }
+    }
+
+    failures
+}
+
+/// Run the regression suite of the move generator and the rules. The GIL is released while running.
+///
+/// Returns the descriptions of the failed cases, which is empty if all cases pass.
+#[pyfunction]
+#[pyo3(name = "run_regression")]
+pub fn py_run_regression(py: Python) -> std::vec::Vec<String> {
+    py.allow_threads(run_regression)
+}
+
+#[test]
+fn regression_test() {
+    assert_eq!(run_regression(), std::vec::Vec::<String>::new());
+}
+
+/// Run by `cargo test --release -- --ignored` after changing the perft cases or the reference move generator.
+#[test]
+#[ignore]
+fn regression_perft_reference_test() {
+    use movegen_reference;
+
+    fn reference_perft(position: &mut Position, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let mut count = 0;
+
+        for m in movegen_reference::generate_moves(position) {
+            position.do_move(&m);
+            count += reference_perft(position, depth - 1);
+            position.undo_move();
+        }
+
+        count
+    }
+
+    // 登録されている perft の値が参照実装とも一致する
+    for case in PERFT_CASES.iter() {
+        let mut position = set_position(case.sfen).unwrap();
+
+        assert_eq!(reference_perft(&mut position, case.depth), case.nodes, "{}", case.name);
+    }
+}
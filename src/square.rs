@@ -0,0 +1,104 @@
+use pyo3::prelude::*;
+
+use position::*;
+use r#move::*;
+use types::*;
+
+/// Return the file (1 to 5) of the square, e.g. 5 for the square `5e`.
+pub fn square_file(square: usize) -> usize {
+    5 - square % 5
+}
+
+/// Return the rank (1 to 5, where 1 is the rank a) of the square, e.g. 5 for the square `5e`.
+pub fn square_rank(square: usize) -> usize {
+    square / 5 + 1
+}
+
+/// Return the square of the file (1 to 5) and the rank (1 to 5, where 1 is the rank a).
+pub fn make_square(file: usize, rank: usize) -> usize {
+    (rank - 1) * 5 + (5 - file)
+}
+
+/// Parse a sfen represented square such as `3c`. Returns `None` if it is malformed.
+pub fn parse_square(sfen: &str) -> Option<usize> {
+    let bytes = sfen.as_bytes();
+
+    if bytes.len() == 2 && (b'1'..=b'5').contains(&bytes[0]) && (b'a'..=b'e').contains(&bytes[1]) {
+        Some(sfen_to_square(sfen))
+    } else {
+        None
+    }
+}
+
+fn check_square(square: usize) -> Result<(), PositionError> {
+    if square >= SQUARE_NB {
+        return Err(PositionError(format!("invalid square {}", square)));
+    }
+
+    Ok(())
+}
+
+/// Return the file (1 to 5) of the square.
+#[pyfunction]
+#[pyo3(name = "square_file", text_signature = "(square)")]
+pub fn py_square_file(square: usize) -> Result<usize, PositionError> {
+    check_square(square)?;
+
+    Ok(square_file(square))
+}
+
+/// Return the rank (1 to 5, where 1 is the rank a) of the square.
+#[pyfunction]
+#[pyo3(name = "square_rank", text_signature = "(square)")]
+pub fn py_square_rank(square: usize) -> Result<usize, PositionError> {
+    check_square(square)?;
+
+    Ok(square_rank(square))
+}
+
+/// Return the square of the file (1 to 5) and the rank (1 to 5, where 1 is the rank a).
+#[pyfunction]
+#[pyo3(name = "make_square", text_signature = "(file, rank)")]
+pub fn py_make_square(file: usize, rank: usize) -> Result<usize, PositionError> {
+    if !(1..=5).contains(&file) || !(1..=5).contains(&rank) {
+        return Err(PositionError(format!("invalid file and rank ({}, {})", file, rank)));
+    }
+
+    Ok(make_square(file, rank))
+}
+
+/// Return the sfen representation of the square, e.g. `5e` for the square 20.
+#[pyfunction]
+#[pyo3(name = "square_to_sfen", text_signature = "(square)")]
+pub fn py_square_to_sfen(square: usize) -> Result<String, PositionError> {
+    check_square(square)?;
+
+    Ok(square_to_sfen(square))
+}
+
+/// Return the square of the sfen representation, e.g. 20 for `5e`.
+#[pyfunction]
+#[pyo3(name = "sfen_to_square", text_signature = "(sfen)")]
+pub fn py_sfen_to_square(sfen: &str) -> Result<usize, PositionError> {
+    parse_square(sfen).ok_or_else(|| PositionError(format!("invalid square `{}`", sfen)))
+}
+
+#[test]
+fn square_test() {
+    for square in 0..SQUARE_NB {
+        let file = square_file(square);
+        let rank = square_rank(square);
+
+        assert!((1..=5).contains(&file));
+        assert!((1..=5).contains(&rank));
+        assert_eq!(make_square(file, rank), square);
+        assert_eq!(parse_square(&square_to_sfen(square)), Some(square));
+        assert_eq!(square_to_sfen(square), format!("{}{}", file, (b'a' + rank as u8 - 1) as char));
+    }
+
+    assert_eq!(parse_square("5e"), Some(20));
+    assert_eq!(parse_square("1a"), Some(4));
+    assert_eq!(parse_square("6a"), None);
+    assert_eq!(parse_square("1f"), None);
+    assert_eq!(parse_square("1a+"), None);
+}
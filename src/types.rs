@@ -496,6 +496,20 @@ pub enum Direction {
     NW = 7,
 }
 
+/// The classification of a repetition (sennichite), from the viewpoint of the side to move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Repetition(pub u8);
+
+impl Repetition {
+    pub const NONE: Repetition = Repetition(0);
+    /// An ordinary repetition, which is a draw.
+    pub const DRAW: Repetition = Repetition(1);
+    /// The opponent has continued checks, which is a win of the side to move.
+    pub const WIN: Repetition = Repetition(2);
+    /// The side to move has continued checks, which is a loss of the side to move.
+    pub const LOSE: Repetition = Repetition(3);
+}
+
 pub const PIECE_ALL: [Piece; 20] = [
     Piece::W_KING,
     Piece::W_GOLD,